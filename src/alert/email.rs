@@ -0,0 +1,168 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+};
+
+use super::{AlertError, Event};
+use crate::check::{connect, DEFAULT_TIMEOUT};
+
+/// Sends an event as an email through a SMTP server.
+///
+/// `to` may hold several addresses separated by commas.
+pub fn send(
+    smtp_host: &str,
+    smtp_port: u16,
+    from: &str,
+    to: &str,
+    event: &Event,
+) -> Result<(), AlertError> {
+    let recipients: Vec<&str> = to.split(',').map(str::trim).collect();
+    let stream = connect(smtp_host, smtp_port, DEFAULT_TIMEOUT)?;
+    let mut smtp = Smtp {
+        rdr: BufReader::new(stream.try_clone()?),
+        stream,
+    };
+
+    smtp.expect(220)?;
+    smtp.command("EHLO griffin", 250)?;
+    smtp.command(&format!("MAIL FROM:<{}>", from), 250)?;
+    for rcpt in &recipients {
+        smtp.command(&format!("RCPT TO:<{}>", rcpt), 250)?;
+    }
+    smtp.command("DATA", 354)?;
+    smtp.command(&message(from, &recipients, event), 250)?;
+    smtp.command("QUIT", 221)
+}
+
+/// Formats an event as a plain text email, ending with the DATA terminator
+fn message(from: &str, to: &[&str], event: &Event) -> String {
+    let mut msg = format!(
+        "From: {}\r\nTo: {}\r\nSubject: [griffin] {} is {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+        from,
+        to.join(", "),
+        event.service,
+        event.status
+    );
+    msg.push_str(&format!("{} ({})\r\n", event.summary(), event.host));
+    msg.push_str(&format!("Time: {} (unix)\r\n", event.timestamp));
+    // lines starting with a dot must be escaped so they don't end the message
+    let msg = msg.replace("\r\n.", "\r\n..");
+    msg + "."
+}
+
+/// A SMTP conversation
+struct Smtp {
+    stream: TcpStream,
+    rdr: BufReader<TcpStream>,
+}
+
+impl Smtp {
+    /// Sends a line and waits for the expected reply code
+    fn command(&mut self, line: &str, code: u16) -> Result<(), AlertError> {
+        write!(self.stream, "{}\r\n", line)?;
+        self.stream.flush()?;
+        self.expect(code)
+    }
+
+    /// Reads a possibly multiline reply and checks its code
+    fn expect(&mut self, code: u16) -> Result<(), AlertError> {
+        loop {
+            let mut line = String::new();
+            if self.rdr.read_line(&mut line)? == 0 {
+                return Err(AlertError::Rejected(
+                    "SMTP server closed the connection".to_owned(),
+                ));
+            }
+            let line = line.trim_end();
+            if line.len() < 3 || line[..3].parse() != Ok(code) {
+                return Err(AlertError::Rejected(format!(
+                    "SMTP server answered {:?}",
+                    line
+                )));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::state::ServiceStatus;
+    use std::{net::TcpListener, sync::mpsc, thread};
+
+    /// Minimal SMTP server accepting a single message
+    fn smtp_server() -> (u16, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut rdr = BufReader::new(stream.try_clone().unwrap());
+            let mut lines = Vec::new();
+            let mut in_data = false;
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if rdr.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_owned();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250 SIZE 1000\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                stream.write_all(reply).unwrap();
+                lines.push(line);
+            }
+            tx.send(lines).unwrap();
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn sends_email() {
+        let (port, rx) = smtp_server();
+        let event = Event {
+            service: "Foo Web Service".to_owned(),
+            host: "foo.example.com".to_owned(),
+            status: ServiceStatus::Down,
+            reason: Some("ping check failed: timed out".to_owned()),
+            timestamp: 1_600_000_000,
+        };
+        send(
+            "127.0.0.1",
+            port,
+            "griffin@foo.com",
+            "webmaster@foo.com, ops@foo.com",
+            &event,
+        )
+        .unwrap();
+
+        let lines = rx.recv().unwrap();
+        assert_eq!(lines[1], "MAIL FROM:<griffin@foo.com>");
+        assert_eq!(lines[2], "RCPT TO:<webmaster@foo.com>");
+        assert_eq!(lines[3], "RCPT TO:<ops@foo.com>");
+        assert!(lines.contains(&"Subject: [griffin] Foo Web Service is DOWN".to_owned()));
+        assert!(lines.contains(
+            &"Foo Web Service is DOWN: ping check failed: timed out (foo.example.com)".to_owned()
+        ));
+        assert_eq!(lines.last().unwrap(), "QUIT");
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    check::{http::UrlError, Failure},
+    config::{Alert, Config, Service},
+    state::{ServiceState, ServiceStatus},
+};
+
+pub mod email;
+pub mod webhook;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// A service changing status
+pub struct Event {
+    pub service: String,
+    pub host: String,
+    pub status: ServiceStatus,
+    pub reason: Option<String>,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl Event {
+    /// Event for a service that just changed to a state
+    pub fn new(service: &Service, state: &ServiceState) -> Self {
+        Self {
+            service: service.name.clone(),
+            host: service.host.clone(),
+            status: state.status,
+            reason: state.reason.clone(),
+            timestamp: state
+                .since
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    /// One line summary of the event
+    pub fn summary(&self) -> String {
+        match &self.reason {
+            Some(reason) => format!("{} is {}: {}", self.service, self.status, reason),
+            None => format!("{} is {}", self.service, self.status),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Error when an alert could not be delivered
+pub enum AlertError {
+    Url(UrlError),
+    /// Could not talk to the receiving server
+    Delivery(Failure),
+    /// Receiving server did not accept the alert
+    Rejected(String),
+}
+
+impl Display for AlertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertError::Url(e) => e.fmt(f),
+            AlertError::Delivery(e) => e.fmt(f),
+            AlertError::Rejected(e) => write!(f, "rejected: {}", e),
+        }
+    }
+}
+
+impl From<Failure> for AlertError {
+    fn from(v: Failure) -> Self {
+        AlertError::Delivery(v)
+    }
+}
+
+impl From<std::io::Error> for AlertError {
+    fn from(v: std::io::Error) -> Self {
+        AlertError::Delivery(v.into())
+    }
+}
+
+impl From<UrlError> for AlertError {
+    fn from(v: UrlError) -> Self {
+        AlertError::Url(v)
+    }
+}
+
+impl Alert {
+    /// Delivers an event
+    pub fn send(&self, event: &Event) -> Result<(), AlertError> {
+        match self {
+            Alert::Email {
+                from,
+                to,
+                smtp_host,
+                smtp_port,
+            } => email::send(smtp_host, *smtp_port, from, to, event),
+            Alert::Webhook { url } => webhook::send(url, event),
+        }
+    }
+}
+
+/// Fires alerts when a service changes status
+pub struct Dispatcher {
+    config: Arc<Config>,
+}
+
+impl Dispatcher {
+    /// Creates a new dispatcher for the alerts of a config
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+
+    /// Sends alerts for a service that changed from a previous status.
+    /// Services found up on their first results don't produce an event.
+    pub fn handle(&self, service: usize, previous: ServiceStatus, state: &ServiceState) {
+        if previous == ServiceStatus::Unknown && state.status == ServiceStatus::Up {
+            return;
+        }
+        self.dispatch(Event::new(&self.config.services[service], state));
+    }
+
+    /// Sends an event to every configured alert in the background
+    pub fn dispatch(&self, event: Event) {
+        info!("{}", event.summary());
+        let config = Arc::clone(&self.config);
+        thread::spawn(move || {
+            for alert in &config.alerts {
+                if let Err(e) = alert.send(&event) {
+                    warn!("could not send alert {:?}: {}", alert, e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn summarizes_events() {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health: []
+        "###;
+        let config = Config::new(config.as_bytes()).unwrap();
+        let state = ServiceState {
+            status: ServiceStatus::Down,
+            since: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            reason: Some("http check failed: unexpected status code 500".to_owned()),
+        };
+        let event = Event::new(&config.services[0], &state);
+        assert_eq!(event.timestamp, 1_600_000_000);
+        assert_eq!(
+            event.summary(),
+            "Foo Web Service is DOWN: http check failed: unexpected status code 500"
+        );
+
+        let state = ServiceState {
+            status: ServiceStatus::Up,
+            since: SystemTime::now(),
+            reason: None,
+        };
+        let event = Event::new(&config.services[0], &state);
+        assert_eq!(event.summary(), "Foo Web Service is UP");
+    }
+}
@@ -0,0 +1,115 @@
+use super::{AlertError, Event};
+use crate::{
+    check::{
+        http::{Request, Url},
+        DEFAULT_TIMEOUT,
+    },
+    config::TlsOptions,
+};
+
+/// Posts an event as JSON to a webhook url
+pub fn send(url: &str, event: &Event) -> Result<(), AlertError> {
+    let url: Url = url.parse()?;
+    let tls = TlsOptions::default();
+    let body = serde_json::to_vec(event).expect("events serialize to json");
+    let response = Request {
+        method: "POST",
+        host: &url.host,
+        port: url.port,
+        path: &url.path,
+        headers: &[("Content-Type", "application/json")],
+        body: &body,
+        tls: if url.scheme == "https" {
+            Some(&tls)
+        } else {
+            None
+        },
+    }
+    .send(DEFAULT_TIMEOUT)?;
+    if !(200..300).contains(&response.status) {
+        return Err(AlertError::Rejected(format!(
+            "webhook answered with status {}",
+            response.status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::state::ServiceStatus;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    fn event() -> Event {
+        Event {
+            service: "Foo Web Service".to_owned(),
+            host: "foo.example.com".to_owned(),
+            status: ServiceStatus::Down,
+            reason: Some("http check failed: timed out".to_owned()),
+            timestamp: 1_600_000_000,
+        }
+    }
+
+    /// Accepts one request, answers with `status` and passes on the request
+    fn serve_once(status: u16) -> (u16, mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut rdr = BufReader::new(stream);
+            let mut head = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                rdr.read_line(&mut line).unwrap();
+                if let Some(v) = line.strip_prefix("Content-Length: ") {
+                    length = v.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let mut body = vec![0; length];
+            rdr.read_exact(&mut body).unwrap();
+            write!(
+                rdr.get_mut(),
+                "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\n\r\n",
+                status
+            )
+            .unwrap();
+            tx.send((head, body)).unwrap();
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn posts_event_as_json() {
+        let (port, rx) = serve_once(204);
+        let url = format!("http://127.0.0.1:{}/hooks/abcd", port);
+        send(&url, &event()).unwrap();
+
+        let (head, body) = rx.recv().unwrap();
+        assert!(head.starts_with("POST /hooks/abcd HTTP/1.1\r\n"));
+        assert!(head.contains("Content-Type: application/json\r\n"));
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["service"], "Foo Web Service");
+        assert_eq!(json["status"], "down");
+        assert_eq!(json["timestamp"], 1_600_000_000);
+    }
+
+    #[test]
+    fn fails_when_webhook_rejects() {
+        let (port, _rx) = serve_once(500);
+        let url = format!("http://127.0.0.1:{}/", port);
+        assert!(matches!(send(&url, &event()), Err(AlertError::Rejected(_))));
+    }
+}
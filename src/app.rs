@@ -1,9 +1,24 @@
 extern crate clap;
 
-use clap::{crate_authors, crate_version, App, Arg};
-use log::info;
+use clap::{crate_authors, crate_version, App, Arg, SubCommand};
+use log::{error, info, warn};
 
-use crate::config::Config;
+use std::{
+    fmt::Write,
+    process,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{
+    alert::Dispatcher,
+    check::Status,
+    config::{Alert, Config, ConfigError, Severity, StatusPolicy},
+    history::{Record, Store},
+    scheduler::{self, Report, Scheduler},
+    server,
+    state::StatusBoard,
+};
 
 pub fn run() {
     env_logger::builder()
@@ -21,12 +36,302 @@ pub fn run() {
                 .value_name("FILE")
                 .help("Path to griffin config file")
                 .default_value("griffin.yaml")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("check-config")
+                .about("Validates the config file and prints a summary without monitoring"),
         )
+        .subcommand(SubCommand::with_name("once").about(
+            "Runs every health check once, prints the results and exits with \
+             0 (ok), 1 (warning), 2 (critical) or 3 (unknown)",
+        ))
         .get_matches();
 
     let config_path = matches.value_of("config").unwrap();
+    if matches.subcommand_matches("check-config").is_some() {
+        process::exit(check_config(config_path));
+    }
+
     info!("Loading config from {}", config_path);
-    let config = Config::new_from_file(config_path).unwrap();
-    println!("{:?}", config);
+    let config = match Config::new_from_file(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+    for problem in config.validate() {
+        warn!("{}", problem);
+    }
+    if matches.subcommand_matches("once").is_some() {
+        process::exit(once(&config));
+    }
+    monitor(Arc::new(config));
+}
+
+/// Runs every health check once and prints a table of the results.
+/// Returns the exit code of the worst result.
+fn once(config: &Config) -> i32 {
+    let reports = scheduler::run_once(config);
+    print!("{}", results_table(config, &reports));
+    reports
+        .iter()
+        .map(|r| r.result.status)
+        .fold(Status::Ok, Status::worst)
+        .exit_code()
+}
+
+/// Formats check reports as a table with a row per check
+fn results_table(config: &Config, reports: &[Report]) -> String {
+    let mut rows = vec![[
+        "SERVICE".to_owned(),
+        "CHECK".to_owned(),
+        "STATUS".to_owned(),
+        "LATENCY".to_owned(),
+        "DETAILS".to_owned(),
+    ]];
+    for report in reports {
+        let service = &config.services[report.service];
+        let result = &report.result;
+        let mut details = Vec::new();
+        if let Some(code) = result.status_code {
+            details.push(format!("HTTP {}", code));
+        }
+        if let Some(loss) = result.packet_loss {
+            details.push(format!("{:.0}% loss", loss));
+        }
+        if let Some(Ok(left)) = result.cert_expiry.map(|e| e.duration_since(report.time)) {
+            details.push(format!(
+                "cert expires in {}d",
+                left.as_secs() / (24 * 60 * 60)
+            ));
+        }
+        if result.attempts > 1 {
+            details.push(format!("after {} attempts", result.attempts));
+        }
+        match (&result.failure, &result.output) {
+            (Some(failure), _) => details.push(failure.to_string()),
+            (None, Some(output)) => details.push(output.clone()),
+            (None, None) => {}
+        }
+        rows.push([
+            service.name.clone(),
+            service.health[report.check].method.to_string(),
+            result.status.to_string(),
+            format!("{}ms", result.latency.as_millis()),
+            details.join(", "),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end()).unwrap();
+    }
+    out
+}
+
+/// Creates the status board of a config, replaying its result history when
+/// it has one so uptime and incidents survive restarts. Exits if the history
+/// can't be opened.
+fn open_history(config: &Arc<Config>) -> (StatusBoard, Option<Store>) {
+    let now = SystemTime::now();
+    let options = match &config.history {
+        Some(options) => options,
+        None => return (StatusBoard::new(Arc::clone(config), now), None),
+    };
+    let (store, records) = match Store::open(options, now)
+        .and_then(|store| store.load().map(|records| (store, records)))
+    {
+        Ok(opened) => opened,
+        Err(e) => {
+            error!("could not open history {}: {}", options.path.display(), e);
+            process::exit(1);
+        }
+    };
+    let start = records.first().map_or(now, Record::time).min(now);
+    let mut board = StatusBoard::new(Arc::clone(config), start);
+    let reports: Vec<Report> = records.iter().filter_map(|r| r.report(config)).collect();
+    for report in &reports {
+        board.replay(report);
+    }
+    info!(
+        "Replayed {} results from {}",
+        reports.len(),
+        options.path.display()
+    );
+    (board, Some(store))
+}
+
+/// Runs every health check forever, logging and recording results, sending
+/// alerts and serving the status page, API and metrics when configured
+fn monitor(config: Arc<Config>) {
+    let alerts = Dispatcher::new(Arc::clone(&config));
+    let (board, mut store) = open_history(&config);
+    let board = Arc::new(Mutex::new(board));
+    if let Some(options) = &config.server {
+        match server::start(options.listen, Arc::clone(&board)) {
+            Ok(addr) => info!("Serving status page, API and metrics on http://{}", addr),
+            Err(e) => {
+                error!("could not listen on {}: {}", options.listen, e);
+                process::exit(1);
+            }
+        }
+    }
+    let reports = Scheduler::new(Arc::clone(&config)).start();
+    for report in reports {
+        let service = &config.services[report.service];
+        let health = &service.health[report.check];
+        match &report.result.failure {
+            None => info!(
+                "{} {} check passed in {:?}",
+                service.name, health.method, report.result.latency
+            ),
+            Some(failure) => warn!(
+                "{} {} check {}: {}",
+                service.name, health.method, report.result.status, failure
+            ),
+        }
+        if let Some(store) = &mut store {
+            if let Err(e) = store.append(&Record::new(&config, &report)) {
+                warn!("could not record result: {}", e);
+            }
+        }
+        let mut board = board.lock().unwrap();
+        if let Some(previous) = board.update(&report) {
+            alerts.handle(report.service, previous, board.service(report.service));
+        }
+    }
+}
+
+/// Loads and validates a config file, printing a summary of it.
+/// Returns the process exit code.
+fn check_config(path: &str) -> i32 {
+    match Config::new_from_file(path) {
+        Ok(config) => {
+            print!("{}", summary(&config));
+            println!("{} is valid", path);
+            0
+        }
+        Err(ConfigError::Invalid(problems)) => {
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+            let errors = problems
+                .iter()
+                .filter(|p| p.severity == Severity::Error)
+                .count();
+            eprintln!("{} is invalid: {} error(s)", path, errors);
+            1
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{} is invalid", path);
+            1
+        }
+    }
+}
+
+/// Human readable description of the services, checks and alerts of a config
+fn summary(config: &Config) -> String {
+    let mut out = String::new();
+    writeln!(out, "Services ({}):", config.services.len()).unwrap();
+    for service in &config.services {
+        write!(out, "  {} ({})", service.name, service.host).unwrap();
+        if service.policy != StatusPolicy::All {
+            write!(out, " policy {}", service.policy).unwrap();
+        }
+        writeln!(out).unwrap();
+        for check in &service.health {
+            write!(out, "    - {}", check.method).unwrap();
+            if let Some(port) = check.port {
+                write!(out, " port {}", port).unwrap();
+            }
+            if let Some(endpoint) = &check.endpoint {
+                write!(out, " {}", endpoint).unwrap();
+            }
+            writeln!(out, " every {}", check.interval).unwrap();
+        }
+    }
+    writeln!(out, "Alerts ({}):", config.alerts.len()).unwrap();
+    for alert in &config.alerts {
+        match alert {
+            Alert::Email {
+                to,
+                smtp_host,
+                smtp_port,
+                ..
+            } => writeln!(out, "  - Email to {} via {}:{}", to, smtp_host, smtp_port),
+            Alert::Webhook { url } => writeln!(out, "  - Webhook {}", url),
+        }
+        .unwrap();
+    }
+    for problem in config.validate() {
+        writeln!(out, "{}", problem).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::{CheckResult, Failure};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn summarize_sample_config() {
+        let config = Config::new_from_file("griffin.yaml").unwrap();
+        let summary = summary(&config);
+        assert!(summary.starts_with("Services (2):\n  Foo Web Service (foo.example.com)\n"));
+        assert!(summary.contains("    - http port 4040 /status every 1h\n"));
+        assert!(summary.contains("    - ping every 30s\n"));
+        assert!(summary.contains("  Bar Web Service (bar.example.com) policy quorum of 2\n"));
+        assert!(summary.contains("    - tls_cert every 12h\n"));
+        assert!(summary.contains("Alerts (2):\n  - Email to webmaster@foo.com via localhost:25\n"));
+        assert!(summary.contains("warning: services[1].health[1].interval"));
+    }
+
+    #[test]
+    fn format_results_table() {
+        let config = Config::new_from_file("griffin.yaml").unwrap();
+        let report = |service, check, result| Report {
+            service,
+            check,
+            time: SystemTime::now(),
+            result,
+        };
+        let mut ok = CheckResult::success(Duration::from_millis(12));
+        ok.status_code = Some(200);
+        let mut cert = CheckResult::success(Duration::from_millis(40));
+        cert.cert_expiry = Some(SystemTime::now() + Duration::from_secs(90 * 24 * 3600 + 60));
+        let reports = vec![
+            report(0, 0, ok),
+            report(0, 2, cert),
+            report(
+                1,
+                1,
+                CheckResult::failure(Duration::from_secs(2), Failure::Timeout),
+            ),
+        ];
+        assert_eq!(
+            results_table(&config, &reports),
+            "SERVICE          CHECK     STATUS    LATENCY  DETAILS\n\
+             Foo Web Service  http      OK        12ms     HTTP 200\n\
+             Foo Web Service  tls_cert  OK        40ms     cert expires in 90d\n\
+             Bar Web Service  ping      CRITICAL  2000ms   timed out\n"
+        );
+    }
 }
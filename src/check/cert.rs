@@ -0,0 +1,304 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rustls::Certificate;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use super::{timeout, tls::connect_tls, CheckResult, Failure, Status, DEFAULT_TIMEOUT};
+use crate::config::{CertExpiry, HealthCheck, TlsOptions};
+
+/// Port used when a tls_cert check does not set one
+pub const DEFAULT_PORT: u16 = 443;
+
+/// Signature algorithms with practical collision attacks, by OID
+const WEAK_SIGNATURES: [(&str, &str); 5] = [
+    ("1.2.840.113549.1.1.2", "md2WithRSAEncryption"),
+    ("1.2.840.113549.1.1.4", "md5WithRSAEncryption"),
+    ("1.2.840.113549.1.1.5", "sha1WithRSAEncryption"),
+    ("1.2.840.10045.4.1", "ecdsa-with-SHA1"),
+    ("1.2.840.10040.4.3", "dsa-with-sha1"),
+];
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Connects to a host over TLS and inspects the certificate chain it presents
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    // the chain is inspected here, so it must not be rejected during the handshake
+    let opts = TlsOptions {
+        client_cert: check.tls.client_cert.clone(),
+        client_key: check.tls.client_key.clone(),
+        server_name: check.tls.server_name.clone(),
+        insecure_skip_verify: true,
+        ..Default::default()
+    };
+    let port = check.port.unwrap_or(DEFAULT_PORT);
+    let stream = match connect_tls(host, port, &opts, timeout(check, DEFAULT_TIMEOUT)) {
+        Ok(stream) => stream,
+        Err(failure) => return CheckResult::failure(start.elapsed(), failure),
+    };
+    let latency = start.elapsed();
+
+    let name = check.tls.server_name.as_deref().unwrap_or(host);
+    match inspect(
+        stream.peer_certificates(),
+        name,
+        &check.expiry,
+        SystemTime::now(),
+    ) {
+        Ok(inspection) => {
+            let mut result = if inspection.problems.is_empty() {
+                CheckResult::success(latency)
+            } else {
+                CheckResult::with_status(
+                    inspection.status,
+                    latency,
+                    Failure::Certificate(inspection.problems.join(", ")),
+                )
+            };
+            result.cert_expiry = Some(inspection.expiry);
+            result
+        }
+        Err(failure) => CheckResult::failure(latency, failure),
+    }
+}
+
+/// What was found out about a certificate chain
+#[derive(Debug)]
+struct Inspection {
+    status: Status,
+    problems: Vec<String>,
+    /// When the first certificate of the chain expires
+    expiry: SystemTime,
+}
+
+impl Inspection {
+    fn problem(&mut self, status: Status, problem: String) {
+        self.status = self.status.worst(status);
+        self.problems.push(problem);
+    }
+}
+
+/// Checks the expiry, name and signatures of a certificate chain, leaf first
+fn inspect(
+    chain: &[Certificate],
+    name: &str,
+    expiry: &CertExpiry,
+    now: SystemTime,
+) -> Result<Inspection, Failure> {
+    let certs = chain
+        .iter()
+        .map(|c| {
+            X509Certificate::from_der(&c.0)
+                .map(|(_, cert)| cert)
+                .map_err(|e| Failure::Tls(format!("invalid certificate: {}", e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let leaf = certs
+        .first()
+        .ok_or_else(|| Failure::Tls("no certificate presented".to_owned()))?;
+
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let (index, expiring) = certs
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| c.validity().not_after.timestamp())
+        .unwrap();
+    let not_after = expiring.validity().not_after.timestamp();
+    let mut inspection = Inspection {
+        status: Status::Ok,
+        problems: Vec::new(),
+        expiry: UNIX_EPOCH + Duration::from_secs(not_after.max(0) as u64),
+    };
+
+    let which = match index {
+        0 => String::new(),
+        _ => format!("{} in chain ", expiring.subject()),
+    };
+    let left = not_after - now;
+    if left <= 0 {
+        inspection.problem(
+            Status::Critical,
+            format!("{}expired {} ago", which, format_days(-left)),
+        );
+    } else if left < expiry.critical.as_duration().as_secs() as i64 {
+        inspection.problem(
+            Status::Critical,
+            format!("{}expires in {}", which, format_days(left)),
+        );
+    } else if left < expiry.warning.as_duration().as_secs() as i64 {
+        inspection.problem(
+            Status::Warning,
+            format!("{}expires in {}", which, format_days(left)),
+        );
+    }
+    if leaf.validity().not_before.timestamp() > now {
+        inspection.problem(Status::Critical, "is not valid yet".to_owned());
+    }
+
+    if !valid_for(leaf, name) {
+        inspection.problem(Status::Critical, format!("is not valid for {}", name));
+    }
+
+    for cert in &certs {
+        // self signed roots are trusted as is, their signature doesn't matter
+        if cert.subject() == cert.issuer() {
+            continue;
+        }
+        if let Some(algorithm) = weak_signature(&cert.signature_algorithm.algorithm.to_id_string())
+        {
+            inspection.problem(
+                Status::Warning,
+                format!("{} is signed with weak {}", cert.subject(), algorithm),
+            );
+        }
+    }
+    Ok(inspection)
+}
+
+/// Name of a signature algorithm when it is weak
+fn weak_signature(oid: &str) -> Option<&'static str> {
+    WEAK_SIGNATURES
+        .iter()
+        .find(|(weak, _)| *weak == oid)
+        .map(|(_, name)| *name)
+}
+
+/// Whether the subject alternative names of a certificate cover a host name or
+/// IP address. Falls back to the common name when there are none.
+fn valid_for(cert: &X509Certificate, name: &str) -> bool {
+    let ip = name.parse::<IpAddr>().ok();
+    match cert.subject_alternative_name() {
+        Ok(Some(san)) => san.value.general_names.iter().any(|gn| match (gn, ip) {
+            (GeneralName::DNSName(pattern), None) => matches_name(pattern, name),
+            (GeneralName::IPAddress(bytes), Some(ip)) => match ip {
+                IpAddr::V4(ip) => *bytes == ip.octets(),
+                IpAddr::V6(ip) => *bytes == ip.octets(),
+            },
+            _ => false,
+        }),
+        _ => cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok())
+            .any(|cn| matches_name(cn, name)),
+    }
+}
+
+/// Matches a host name against a certificate name, which may start with a
+/// wildcard label like `*.example.com`
+fn matches_name(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_lowercase();
+    let name = name.trim_end_matches('.').to_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(parent) => match name.split_once('.') {
+            Some((label, rest)) => !label.is_empty() && rest == parent,
+            None => false,
+        },
+        None => pattern == name,
+    }
+}
+
+/// Formats seconds as whole days, or hours when less than a day
+fn format_days(secs: i64) -> String {
+    let secs = secs as u64;
+    if secs >= DAY {
+        format!("{}d", secs / DAY)
+    } else {
+        format!("{}h", secs / 3600)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::tls::testing::{server_params, TestPki};
+    use time::OffsetDateTime;
+
+    fn cert_check(port: u16, extra: &str) -> HealthCheck {
+        let check = format!("{{method: tls_cert, port: {}{}}}", port, extra);
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    /// Serves a certificate for localhost that expires after `days`
+    fn serve_expiring(days: i64) -> u16 {
+        let mut params = server_params();
+        params.not_before = OffsetDateTime::now_utc() - time::Duration::days(30);
+        // an hour of slack keeps the whole days stable while the test runs
+        params.not_after =
+            OffsetDateTime::now_utc() + time::Duration::days(days) + time::Duration::hours(1);
+        TestPki::with_server(params).serve("", false)
+    }
+
+    #[test]
+    fn valid_certificate() {
+        let port = TestPki::new().serve("", false);
+        let result = check("localhost", &cert_check(port, ""));
+        assert_eq!(result.status, Status::Ok, "{:?}", result.failure);
+        assert!(result.cert_expiry.unwrap() > SystemTime::now() + Duration::from_secs(365 * DAY));
+
+        let result = check("127.0.0.1", &cert_check(port, ""));
+        assert_eq!(result.status, Status::Ok, "{:?}", result.failure);
+    }
+
+    #[test]
+    fn expiring_certificates() {
+        let result = check("localhost", &cert_check(serve_expiring(10), ""));
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.failure,
+            Some(Failure::Certificate("expires in 10d".to_owned()))
+        );
+
+        let result = check("localhost", &cert_check(serve_expiring(3), ""));
+        assert_eq!(result.status, Status::Critical);
+
+        let port = serve_expiring(3);
+        let result = check("localhost", &cert_check(port, ", expiry: {critical: 1d}"));
+        assert_eq!(result.status, Status::Warning);
+
+        let result = check("localhost", &cert_check(serve_expiring(-3), ""));
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.failure,
+            Some(Failure::Certificate("expired 2d ago".to_owned()))
+        );
+    }
+
+    #[test]
+    fn certificate_for_another_name() {
+        let port = TestPki::new().serve("", false);
+        let check = cert_check(port, ", tls: {server_name: api.example.com}");
+        let result = super::check("127.0.0.1", &check);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.failure,
+            Some(Failure::Certificate(
+                "is not valid for api.example.com".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn match_names() {
+        assert!(matches_name("example.com", "Example.COM."));
+        assert!(matches_name("*.example.com", "api.example.com"));
+        assert!(!matches_name("*.example.com", "example.com"));
+        assert!(!matches_name("*.example.com", "a.b.example.com"));
+        assert!(!matches_name("api.example.com", "www.example.com"));
+    }
+
+    #[test]
+    fn detect_weak_signatures() {
+        assert_eq!(
+            weak_signature("1.2.840.113549.1.1.5"),
+            Some("sha1WithRSAEncryption")
+        );
+        // sha256WithRSAEncryption and ecdsa-with-SHA256
+        assert_eq!(weak_signature("1.2.840.113549.1.1.11"), None);
+        assert_eq!(weak_signature("1.2.840.10045.4.3.2"), None);
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use super::{timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::{DnsOptions, HealthCheck, RecordType};
+
+/// Port DNS servers listen on
+pub const DEFAULT_PORT: u16 = 53;
+
+/// Where the system resolver is configured
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Largest DNS message sent over UDP without EDNS
+const MAX_UDP_SIZE: usize = 512;
+
+/// Most compression pointers followed in a single name
+const MAX_POINTERS: usize = 16;
+
+const FLAG_RD: u16 = 0x0100;
+const FLAG_TC: u16 = 0x0200;
+const CLASS_IN: u16 = 1;
+
+/// Looks up a record of the host, or of the configured name, and checks the
+/// values that come back
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let opts = &check.dns;
+    let name = opts.name.as_deref().unwrap_or(host);
+    let resolver = match &opts.resolver {
+        Some(resolver) => match resolver_addr(resolver) {
+            Some(addr) => addr,
+            None => {
+                let failure = Failure::Resolve(format!("invalid resolver {}", resolver));
+                return CheckResult::failure(start.elapsed(), failure);
+            }
+        },
+        None => system_resolver(),
+    };
+
+    let records = lookup(
+        resolver,
+        name,
+        opts.record_type,
+        timeout(check, DEFAULT_TIMEOUT),
+    );
+    let latency = start.elapsed();
+    match records.and_then(|records| verify(&records, latency, name, opts)) {
+        Ok(()) => CheckResult::success(latency),
+        Err(failure) => CheckResult::failure(latency, failure),
+    }
+}
+
+/// Parses a resolver address, which may leave out the port
+pub fn resolver_addr(s: &str) -> Option<SocketAddr> {
+    s.parse().ok().or_else(|| {
+        s.parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, DEFAULT_PORT))
+    })
+}
+
+/// First nameserver of resolv.conf, or a local one when there is none
+fn system_resolver() -> SocketAddr {
+    fs::read_to_string(RESOLV_CONF)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .find_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, DEFAULT_PORT))
+        .unwrap_or_else(|| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT))
+}
+
+/// Checks looked up records against the expectations of a check
+fn verify(
+    records: &[String],
+    latency: Duration,
+    name: &str,
+    opts: &DnsOptions,
+) -> Result<(), Failure> {
+    if records.is_empty() {
+        return Err(Failure::Assertion(format!(
+            "no {} records for {}",
+            opts.record_type, name
+        )));
+    }
+    for expected in &opts.expect {
+        if !records
+            .iter()
+            .any(|r| same_value(opts.record_type, r, expected))
+        {
+            return Err(Failure::Assertion(format!(
+                "{} records for {} do not include {:?}, got {}",
+                opts.record_type,
+                name,
+                expected,
+                records.join(", ")
+            )));
+        }
+    }
+    if let Some(max) = &opts.max_resolution_time {
+        if latency > max.as_duration() {
+            return Err(Failure::Assertion(format!(
+                "resolution took {}ms, more than {}",
+                latency.as_millis(),
+                max
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Compares a record with an expected value. Addresses are compared parsed and
+/// names without case or trailing dot.
+fn same_value(record_type: RecordType, record: &str, expected: &str) -> bool {
+    match record_type {
+        RecordType::A | RecordType::Aaaa => match expected.parse::<IpAddr>() {
+            Ok(ip) => record.parse() == Ok(ip),
+            Err(_) => false,
+        },
+        RecordType::Cname | RecordType::Mx => {
+            let normalize = |s: &str| s.trim().trim_end_matches('.').to_lowercase();
+            normalize(record) == normalize(expected)
+        }
+        RecordType::Txt => record == expected,
+    }
+}
+
+/// Code of a record type on the wire
+fn type_code(record_type: RecordType) -> u16 {
+    match record_type {
+        RecordType::A => 1,
+        RecordType::Cname => 5,
+        RecordType::Mx => 15,
+        RecordType::Txt => 16,
+        RecordType::Aaaa => 28,
+    }
+}
+
+/// Asks a DNS server for the records of a name over UDP, retrying over TCP
+/// when the answer doesn't fit
+fn lookup(
+    resolver: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> Result<Vec<String>, Failure> {
+    let id = RandomState::new().build_hasher().finish() as u16;
+    let query = encode_query(id, name, record_type)?;
+    let mut response = query_udp(resolver, &query, id, timeout)?;
+    if u16::from_be_bytes([response[2], response[3]]) & FLAG_TC != 0 {
+        response = query_tcp(resolver, &query, timeout)?;
+    }
+    parse_response(&response, id, name, record_type)
+}
+
+/// Sends a query over UDP and waits for the response with the same id
+fn query_udp(
+    resolver: SocketAddr,
+    query: &[u8],
+    id: u16,
+    timeout: Duration,
+) -> Result<Vec<u8>, Failure> {
+    let local: SocketAddr = match resolver {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(resolver).map_err(connect_failure)?;
+    socket.send(query).map_err(connect_failure)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0; MAX_UDP_SIZE];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            return Err(Failure::Timeout);
+        }
+        socket.set_read_timeout(Some(left))?;
+        let n = socket.recv(&mut buf).map_err(connect_failure)?;
+        // stray answers to earlier queries are dropped
+        if n >= 12 && buf[..2] == id.to_be_bytes() {
+            return Ok(buf[..n].to_vec());
+        }
+    }
+}
+
+/// Sends a query over TCP, where messages are prefixed by their length
+fn query_tcp(resolver: SocketAddr, query: &[u8], timeout: Duration) -> Result<Vec<u8>, Failure> {
+    let mut stream = TcpStream::connect_timeout(&resolver, timeout).map_err(connect_failure)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut msg = (query.len() as u16).to_be_bytes().to_vec();
+    msg.extend_from_slice(query);
+    stream.write_all(&msg)?;
+
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut response = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut response)?;
+    Ok(response)
+}
+
+fn connect_failure(e: io::Error) -> Failure {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused => Failure::Connect(e.to_string()),
+        _ => e.into(),
+    }
+}
+
+/// Builds a recursive query for a single question
+fn encode_query(id: u16, name: &str, record_type: RecordType) -> Result<Vec<u8>, Failure> {
+    let mut query = Vec::with_capacity(MAX_UDP_SIZE);
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&FLAG_RD.to_be_bytes());
+    // one question, no answer, authority or additional records
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    encode_name(&mut query, name)?;
+    query.extend_from_slice(&type_code(record_type).to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Appends a name as length prefixed labels
+fn encode_name(buf: &mut Vec<u8>, name: &str) -> Result<(), Failure> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Failure::Resolve(format!("invalid name {:?}", name)));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// Gets the values of the answers of a record type out of a response
+fn parse_response(
+    msg: &[u8],
+    id: u16,
+    name: &str,
+    record_type: RecordType,
+) -> Result<Vec<String>, Failure> {
+    let u16_at = |pos: usize| -> Result<u16, Failure> {
+        msg.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(truncated)
+    };
+    if u16_at(0)? != id {
+        return Err(Failure::Protocol(
+            "DNS response for another query".to_owned(),
+        ));
+    }
+    let rcode = u16_at(2)? & 0x000f;
+    if rcode != 0 {
+        return Err(Failure::Resolve(format!(
+            "{} for {}",
+            rcode_name(rcode),
+            name
+        )));
+    }
+
+    let mut pos = 12;
+    for _ in 0..u16_at(4)? {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..u16_at(6)? {
+        pos = read_name(msg, pos)?.1;
+        let (rtype, class) = (u16_at(pos)?, u16_at(pos + 2)?);
+        let len = u16_at(pos + 8)? as usize;
+        let start = pos + 10;
+        let rdata = msg.get(start..start + len).ok_or_else(truncated)?;
+        if rtype == type_code(record_type) && class == CLASS_IN {
+            records.push(decode_rdata(msg, start, rdata, record_type)?);
+        }
+        pos = start + len;
+    }
+    Ok(records)
+}
+
+/// Formats the data of a record the way it is written in zone files
+fn decode_rdata(
+    msg: &[u8],
+    start: usize,
+    rdata: &[u8],
+    record_type: RecordType,
+) -> Result<String, Failure> {
+    let invalid = || Failure::Protocol(format!("invalid {} record", record_type));
+    match record_type {
+        RecordType::A => <[u8; 4]>::try_from(rdata)
+            .map(|ip| Ipv4Addr::from(ip).to_string())
+            .map_err(|_| invalid()),
+        RecordType::Aaaa => <[u8; 16]>::try_from(rdata)
+            .map(|ip| Ipv6Addr::from(ip).to_string())
+            .map_err(|_| invalid()),
+        RecordType::Cname => Ok(read_name(msg, start)?.0),
+        RecordType::Mx => {
+            let preference = rdata.get(..2).ok_or_else(invalid)?;
+            let preference = u16::from_be_bytes([preference[0], preference[1]]);
+            Ok(format!("{} {}", preference, read_name(msg, start + 2)?.0))
+        }
+        RecordType::Txt => {
+            let mut text = Vec::new();
+            let mut rest = rdata;
+            while let Some((&len, tail)) = rest.split_first() {
+                let part = tail.get(..len as usize).ok_or_else(invalid)?;
+                text.extend_from_slice(part);
+                rest = &tail[len as usize..];
+            }
+            Ok(String::from_utf8_lossy(&text).into_owned())
+        }
+    }
+}
+
+/// Reads a possibly compressed name, returning it along with the position
+/// right after it
+fn read_name(msg: &[u8], mut pos: usize) -> Result<(String, usize), Failure> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *msg.get(pos).ok_or_else(truncated)? as usize;
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                pos += 1;
+                break;
+            }
+            0x00 => {
+                let label = msg.get(pos + 1..pos + 1 + len).ok_or_else(truncated)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            0xc0 => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return Err(Failure::Protocol("DNS name compression loop".to_owned()));
+                }
+                let low = *msg.get(pos + 1).ok_or_else(truncated)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | low;
+            }
+            _ => return Err(Failure::Protocol("invalid DNS label".to_owned())),
+        }
+    }
+    Ok((labels.join("."), end.unwrap_or(pos)))
+}
+
+fn truncated() -> Failure {
+    Failure::Protocol("truncated DNS message".to_owned())
+}
+
+/// Name of a DNS response code
+fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        1 => "FORMERR".to_owned(),
+        2 => "SERVFAIL".to_owned(),
+        3 => "NXDOMAIN".to_owned(),
+        4 => "NOTIMP".to_owned(),
+        5 => "REFUSED".to_owned(),
+        _ => format!("RCODE {}", rcode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    /// Records served by the stub server, as name, type and data
+    type Zone = Vec<(&'static str, u16, Vec<u8>)>;
+
+    fn zone() -> Zone {
+        let mut cname = Vec::new();
+        encode_name(&mut cname, "web.example.com").unwrap();
+        let mut mx = vec![0, 10];
+        encode_name(&mut mx, "mail.example.com").unwrap();
+        vec![
+            ("example.com", 1, vec![10, 0, 0, 1]),
+            ("example.com", 1, vec![10, 0, 0, 2]),
+            ("example.com", 28, Ipv6Addr::LOCALHOST.octets().to_vec()),
+            ("example.com", 15, mx),
+            ("example.com", 16, b"\x0bv=spf1 -all\x03 ok".to_vec()),
+            ("www.example.com", 5, cname),
+        ]
+    }
+
+    /// Answers a query out of a zone, pointing back at the question for names
+    fn answer(query: &[u8], zone: &Zone, truncate: bool) -> Vec<u8> {
+        let (name, pos) = read_name(query, 12).unwrap();
+        let qtype = u16::from_be_bytes([query[pos], query[pos + 1]]);
+        let known = zone.iter().any(|(n, _, _)| *n == name);
+        let answers: Vec<_> = zone
+            .iter()
+            .filter(|(n, t, _)| *n == name && *t == qtype && !truncate)
+            .collect();
+
+        let mut flags: u16 = 0x8180;
+        if !known {
+            flags |= 3;
+        }
+        if truncate {
+            flags |= FLAG_TC;
+        }
+        let mut msg = query[..2].to_vec();
+        msg.extend_from_slice(&flags.to_be_bytes());
+        msg.extend_from_slice(&[0, 1]);
+        msg.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&[0, 0, 0, 0]);
+        msg.extend_from_slice(&query[12..pos + 4]);
+        for (_, rtype, rdata) in answers {
+            msg.extend_from_slice(&[0xc0, 12]);
+            msg.extend_from_slice(&rtype.to_be_bytes());
+            msg.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+            msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            msg.extend_from_slice(rdata);
+        }
+        msg
+    }
+
+    /// Serves a zone over UDP and TCP on the same local port. UDP answers are
+    /// truncated when asked to, so clients have to retry over TCP.
+    fn serve_zone(truncate_udp: bool) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let listener = TcpListener::bind(addr).unwrap();
+        thread::spawn(move || {
+            let zone = zone();
+            let mut buf = [0; MAX_UDP_SIZE];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let _ = socket.send_to(&answer(&buf[..n], &zone, truncate_udp), peer);
+            }
+        });
+        thread::spawn(move || {
+            let zone = zone();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut len = [0; 2];
+                stream.read_exact(&mut len).unwrap();
+                let mut query = vec![0; u16::from_be_bytes(len) as usize];
+                stream.read_exact(&mut query).unwrap();
+                let msg = answer(&query, &zone, false);
+                stream.write_all(&(msg.len() as u16).to_be_bytes()).unwrap();
+                stream.write_all(&msg).unwrap();
+            }
+        });
+        addr
+    }
+
+    fn dns_check(resolver: SocketAddr, extra: &str) -> HealthCheck {
+        let check = format!(
+            "{{method: dns, dns: {{resolver: '{}'{}}}}}",
+            resolver, extra
+        );
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn looks_up_records() {
+        let resolver = serve_zone(false);
+        let timeout = Duration::from_secs(5);
+        let lookup = |name, record_type| lookup(resolver, name, record_type, timeout);
+        assert_eq!(
+            lookup("example.com", RecordType::A),
+            Ok(vec!["10.0.0.1".to_owned(), "10.0.0.2".to_owned()])
+        );
+        assert_eq!(
+            lookup("example.com", RecordType::Aaaa),
+            Ok(vec!["::1".to_owned()])
+        );
+        assert_eq!(
+            lookup("example.com", RecordType::Mx),
+            Ok(vec!["10 mail.example.com".to_owned()])
+        );
+        assert_eq!(
+            lookup("example.com", RecordType::Txt),
+            Ok(vec!["v=spf1 -all ok".to_owned()])
+        );
+        assert_eq!(
+            lookup("www.example.com.", RecordType::Cname),
+            Ok(vec!["web.example.com".to_owned()])
+        );
+        assert_eq!(
+            lookup("missing.example.com", RecordType::A),
+            Err(Failure::Resolve(
+                "NXDOMAIN for missing.example.com".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn retries_truncated_answers_over_tcp() {
+        let resolver = serve_zone(true);
+        assert_eq!(
+            lookup(
+                resolver,
+                "example.com",
+                RecordType::A,
+                Duration::from_secs(5)
+            ),
+            Ok(vec!["10.0.0.1".to_owned(), "10.0.0.2".to_owned()])
+        );
+    }
+
+    #[test]
+    fn checks_expected_records() {
+        let resolver = serve_zone(false);
+        let check = dns_check(resolver, ", expect: [10.0.0.2]");
+        assert!(super::check("example.com", &check).is_success());
+
+        let check = dns_check(
+            resolver,
+            ", name: www.example.com, record_type: CNAME, expect: [Web.Example.com.]",
+        );
+        assert!(super::check("example.com", &check).is_success());
+
+        let check = dns_check(resolver, ", expect: [10.0.0.3]");
+        assert_eq!(
+            super::check("example.com", &check).failure,
+            Some(Failure::Assertion(
+                "A records for example.com do not include \"10.0.0.3\", got 10.0.0.1, 10.0.0.2"
+                    .to_owned()
+            ))
+        );
+
+        let check = dns_check(resolver, ", record_type: MX");
+        assert_eq!(
+            super::check("www.example.com", &check).failure,
+            Some(Failure::Assertion(
+                "no MX records for www.example.com".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_resolver_addresses() {
+        assert_eq!(
+            resolver_addr("10.0.0.2"),
+            Some("10.0.0.2:53".parse().unwrap())
+        );
+        assert_eq!(
+            resolver_addr("[::1]:5353"),
+            Some("[::1]:5353".parse().unwrap())
+        );
+        assert_eq!(resolver_addr("dns.example.com"), None);
+    }
+
+    #[test]
+    fn reject_compression_loops() {
+        let msg = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc0, 12];
+        assert!(matches!(read_name(&msg, 12), Err(Failure::Protocol(_))));
+    }
+}
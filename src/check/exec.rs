@@ -0,0 +1,341 @@
+use std::{
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{timeout, CheckResult, Failure, Status, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// How often a running command is polled for its exit
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Most output read from a command, the rest is thrown away
+const MAX_OUTPUT_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single performance data metric of a Nagios plugin, written as
+/// `'label'=value[unit];[warn];[crit];[min];[max]`
+pub struct PerfData {
+    pub label: String,
+    pub value: f64,
+    pub unit: String,
+    /// Warning range, kept as written like `10:20` or `@5`
+    pub warn: Option<String>,
+    /// Critical range, kept as written
+    pub crit: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Runs the command of a check like Nagios runs a plugin. The exit code gives
+/// the status and stdout the output and performance data.
+pub fn check(_host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    // validation makes sure exec checks have a command
+    let command = check.command.as_deref().unwrap_or_default();
+    let (code, stdout) = match run(command, &check.args, timeout(check, DEFAULT_TIMEOUT)) {
+        Ok(done) => done,
+        Err(failure) => return CheckResult::failure(start.elapsed(), failure),
+    };
+    let latency = start.elapsed();
+    let (output, perfdata) = parse_output(&stdout);
+    let status = match code {
+        Some(0) => Status::Ok,
+        Some(1) => Status::Warning,
+        Some(2) => Status::Critical,
+        _ => Status::Unknown,
+    };
+    let mut result = match status {
+        Status::Ok => CheckResult::success(latency),
+        _ if output.is_empty() => {
+            let reason = match code {
+                Some(code) => format!("{} exited with {}", command, code),
+                None => format!("{} was killed by a signal", command),
+            };
+            CheckResult::with_status(status, latency, Failure::Command(reason))
+        }
+        _ => CheckResult::with_status(status, latency, Failure::Command(output.clone())),
+    };
+    if !output.is_empty() {
+        result.output = Some(output);
+    }
+    result.perfdata = perfdata;
+    result
+}
+
+/// Kills the process group a command was started in, which takes down the
+/// processes it left running in the background too
+fn kill_group(child: &Child) {
+    // SAFETY: kill has no memory safety requirements. The command leads its
+    // group, so the group id is its pid, and it isn't reaped yet, so neither
+    // can have been reused.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Whether a command exited, leaving it unreaped so its pid stays taken
+fn exited(child: &Child) -> io::Result<bool> {
+    // SAFETY: siginfo_t is plain data, and a zeroed one reads as no child
+    // having exited when waitid doesn't fill it in
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: info is a valid siginfo_t for waitid to write to
+    let ret = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            child.id() as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: waitid succeeded, so info is initialized
+    Ok(unsafe { info.si_pid() } != 0)
+}
+
+/// Runs a command to completion, killing it when it takes longer than the
+/// timeout. Processes it leaves running are killed once it exits so they
+/// can't hold its stdout open. Returns its exit code and at most
+/// `MAX_OUTPUT_SIZE` bytes of stdout.
+fn run(
+    command: &str,
+    args: &[String],
+    timeout: Duration,
+) -> Result<(Option<i32>, String), Failure> {
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| Failure::Command(format!("could not run {}: {}", command, e)))?;
+
+    // read on another thread so a chatty command can't block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = (&mut stdout).take(MAX_OUTPUT_SIZE).read_to_end(&mut out);
+        let _ = tx.send(out);
+        // keep the pipe open until the command is done writing
+        let _ = io::copy(&mut stdout, &mut io::sink());
+    });
+
+    let deadline = Instant::now() + timeout;
+    // the group is killed before the command is reaped, since its pid could
+    // be reused by another group after that
+    while !exited(&child)? {
+        if Instant::now() >= deadline {
+            kill_group(&child);
+            let _ = child.wait();
+            return Err(Failure::Timeout);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    kill_group(&child);
+    let status = child.wait()?;
+    // a process that left the group could still hold stdout open
+    let left = deadline.saturating_duration_since(Instant::now());
+    let out = rx
+        .recv_timeout(left.max(POLL_INTERVAL))
+        .map_err(|_| Failure::Timeout)?;
+    Ok((status.code(), String::from_utf8_lossy(&out).into_owned()))
+}
+
+/// Splits plugin output into its first line of text and the performance data,
+/// which follows a `|` on the first line and on the long output lines
+fn parse_output(stdout: &str) -> (String, Vec<PerfData>) {
+    let mut lines = stdout.lines();
+    let first = lines.next().unwrap_or_default();
+    let (text, perf) = first.split_once('|').unwrap_or((first, ""));
+    let mut perfdata = parse_perfdata(perf);
+    if let Some((_, perf)) = lines.collect::<Vec<_>>().join("\n").split_once('|') {
+        perfdata.extend(parse_perfdata(&perf.replace('\n', " ")));
+    }
+    (text.trim().to_owned(), perfdata)
+}
+
+/// Parses space separated performance data, skipping malformed metrics
+fn parse_perfdata(perf: &str) -> Vec<PerfData> {
+    let mut metrics = Vec::new();
+    let mut rest = perf.trim_start();
+    while !rest.is_empty() {
+        // labels with spaces are quoted, and quotes in them doubled
+        let (label, after) = if let Some(quoted) = rest.strip_prefix('\'') {
+            let mut label = String::new();
+            let mut chars = quoted.char_indices().peekable();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                if c == '\'' {
+                    if let Some((_, '\'')) = chars.peek() {
+                        chars.next();
+                    } else {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                label.push(c);
+            }
+            (label, &quoted[end..])
+        } else {
+            let end = rest.find('=').unwrap_or(rest.len());
+            (rest[..end].to_owned(), &rest[end..])
+        };
+        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+        if let Some(metric) = after[..end]
+            .strip_prefix('=')
+            .and_then(|fields| parse_metric(label, fields))
+        {
+            metrics.push(metric);
+        }
+        rest = after[end..].trim_start();
+    }
+    metrics
+}
+
+/// Parses `value[unit];[warn];[crit];[min];[max]`
+fn parse_metric(label: String, fields: &str) -> Option<PerfData> {
+    let mut fields = fields.split(';');
+    let value = fields.next()?;
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(value.len());
+    let optional = |field: Option<&str>| field.filter(|f| !f.is_empty()).map(str::to_owned);
+    let number = |field: Option<&str>| field.and_then(|f| f.parse().ok());
+    Some(PerfData {
+        label,
+        value: value[..split].parse().ok()?,
+        unit: value[split..].to_owned(),
+        warn: optional(fields.next()),
+        crit: optional(fields.next()),
+        min: number(fields.next()),
+        max: number(fields.next()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn exec_check(script: &str, extra: &str) -> HealthCheck {
+        let check = format!(
+            "{{method: exec, command: sh, args: [-c, {:?}]{}}}",
+            script, extra
+        );
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn maps_exit_codes() {
+        let result = check("", &exec_check("echo 'OK - all good'", ""));
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(result.output.as_deref(), Some("OK - all good"));
+
+        let script = "echo 'DISK WARNING - 10% free | /=90%;80;95;0;100'; exit 1";
+        let result = check("", &exec_check(script, ""));
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(
+            result.failure,
+            Some(Failure::Command("DISK WARNING - 10% free".to_owned()))
+        );
+        assert_eq!(result.perfdata.len(), 1);
+
+        let result = check("", &exec_check("exit 2", ""));
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.failure,
+            Some(Failure::Command("sh exited with 2".to_owned()))
+        );
+
+        for code in &[3, 42] {
+            let result = check("", &exec_check(&format!("exit {}", code), ""));
+            assert_eq!(result.status, Status::Unknown);
+        }
+    }
+
+    #[test]
+    fn kills_commands_that_time_out() {
+        let start = Instant::now();
+        let result = check("", &exec_check("sleep 5", ", timeout: 100ms"));
+        assert_eq!(result.failure, Some(Failure::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // background processes are killed along with the command
+        let start = Instant::now();
+        let result = check("", &exec_check("sleep 5 & sleep 5", ", timeout: 100ms"));
+        assert_eq!(result.failure, Some(Failure::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn does_not_wait_for_background_processes() {
+        let start = Instant::now();
+        let result = check("", &exec_check("sleep 5 & echo OK", ", timeout: 3s"));
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(result.output.as_deref(), Some("OK"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn caps_output() {
+        let args = ["-c".to_owned(), "yes | head -c 1000000".to_owned()];
+        let (code, stdout) = run("sh", &args, Duration::from_secs(3)).unwrap();
+        assert_eq!(code, Some(0));
+        assert_eq!(stdout.len() as u64, MAX_OUTPUT_SIZE);
+        assert!(stdout.starts_with("y\ny\n"));
+    }
+
+    #[test]
+    fn fails_on_missing_command() {
+        let check: HealthCheck =
+            serde_yaml::from_str("{method: exec, command: griffin-no-such-plugin}").unwrap();
+        let result = super::check("", &check);
+        assert_eq!(result.status, Status::Critical);
+        assert!(matches!(result.failure, Some(Failure::Command(_))));
+    }
+
+    #[test]
+    fn parse_plugin_output() {
+        let stdout = "DISK OK - free space: / 3326 MB (56%); | /=2643MB;5948;5958;0;5968\n\
+                      / 15272 MB (77%);\n\
+                      /boot 68 MB (69%); | /boot=68MB;88;93;0;98\n\
+                      'home dir'=69.5%;;;; time=0.02s\n";
+        let (text, perfdata) = parse_output(stdout);
+        assert_eq!(text, "DISK OK - free space: / 3326 MB (56%);");
+        assert_eq!(
+            perfdata[0],
+            PerfData {
+                label: "/".to_owned(),
+                value: 2643.0,
+                unit: "MB".to_owned(),
+                warn: Some("5948".to_owned()),
+                crit: Some("5958".to_owned()),
+                min: Some(0.0),
+                max: Some(5968.0),
+            }
+        );
+        let labels: Vec<&str> = perfdata.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, vec!["/", "/boot", "home dir", "time"]);
+        assert_eq!(perfdata[2].value, 69.5);
+        assert_eq!(perfdata[2].warn, None);
+        assert_eq!(perfdata[3].unit, "s");
+    }
+
+    #[test]
+    fn parse_quoted_labels() {
+        let perfdata = parse_perfdata("'it''s'=1 bad=x ok=-2.5c;@1:2");
+        assert_eq!(perfdata.len(), 2);
+        assert_eq!(perfdata[0].label, "it's");
+        assert_eq!(perfdata[1].value, -2.5);
+        assert_eq!(perfdata[1].unit, "c");
+        assert_eq!(perfdata[1].warn.as_deref(), Some("@1:2"));
+    }
+}
@@ -0,0 +1,650 @@
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    io::{self, Read, Write},
+    time::Instant,
+};
+
+use hpack::{Decoder, Encoder};
+
+use super::{
+    connect, http, percent_decode, timeout, tls::connect_tls_alpn, CheckResult, Failure, Status,
+    DEFAULT_TIMEOUT,
+};
+use crate::config::{HealthCheck, Scheme};
+
+/// Path of the standard `grpc.health.v1.Health/Check` RPC
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// First bytes an HTTP/2 client sends on a connection
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Largest frame accepted, the default SETTINGS_MAX_FRAME_SIZE
+const MAX_FRAME_SIZE: usize = 16 * 1024;
+
+/// Largest response body read from a host
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Largest header block read from a host, before it is decoded
+const MAX_HEADER_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Stream the health check request is sent on
+const STREAM_ID: u32 = 1;
+
+const FRAME_DATA: u8 = 0x0;
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_RST_STREAM: u8 = 0x3;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_PING: u8 = 0x6;
+const FRAME_GOAWAY: u8 = 0x7;
+const FRAME_CONTINUATION: u8 = 0x9;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_ACK: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+/// Names of gRPC status codes, indexed by code
+const GRPC_CODES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Status a gRPC health service reports
+enum ServingStatus {
+    Unknown,
+    Serving,
+    NotServing,
+    ServiceUnknown,
+}
+
+impl From<u64> for ServingStatus {
+    fn from(status: u64) -> Self {
+        match status {
+            1 => ServingStatus::Serving,
+            2 => ServingStatus::NotServing,
+            3 => ServingStatus::ServiceUnknown,
+            _ => ServingStatus::Unknown,
+        }
+    }
+}
+
+impl Display for ServingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            ServingStatus::Unknown => "UNKNOWN",
+            ServingStatus::Serving => "SERVING",
+            ServingStatus::NotServing => "NOT_SERVING",
+            ServingStatus::ServiceUnknown => "SERVICE_UNKNOWN",
+        };
+        f.write_str(status)
+    }
+}
+
+/// A single HTTP/2 frame
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream: u32,
+    payload: Vec<u8>,
+}
+
+/// Calls the standard gRPC health checking RPC of a host
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    // validation makes sure grpc checks have a port
+    let port = check.port.unwrap_or_default();
+    let service = check.grpc_service.as_deref().unwrap_or_default();
+    let default_port = match check.scheme {
+        Scheme::Http => http::DEFAULT_PORT,
+        Scheme::Https => http::DEFAULT_TLS_PORT,
+    };
+    let authority = http::authority(host, port, default_port);
+
+    let timeout = timeout(check, DEFAULT_TIMEOUT);
+    let status = match check.scheme {
+        Scheme::Http => connect(host, port, timeout)
+            .and_then(|mut stream| call(&mut stream, "http", &authority, service)),
+        Scheme::Https => connect_tls_alpn(host, port, &check.tls, &[b"h2"], timeout)
+            .and_then(|mut stream| call(&mut stream, "https", &authority, service)),
+    };
+    let latency = start.elapsed();
+    match status {
+        Ok(ServingStatus::Serving) => CheckResult::success(latency),
+        Ok(ServingStatus::Unknown) => CheckResult::with_status(
+            Status::Unknown,
+            latency,
+            Failure::Grpc("service status is UNKNOWN".to_owned()),
+        ),
+        Ok(status) => {
+            CheckResult::failure(latency, Failure::Grpc(format!("service is {}", status)))
+        }
+        Err(failure) => CheckResult::failure(latency, failure),
+    }
+}
+
+/// Sends a health check request over a fresh HTTP/2 connection and reads the
+/// serving status out of the response
+fn call<S: Read + Write>(
+    stream: &mut S,
+    scheme: &str,
+    authority: &str,
+    service: &str,
+) -> Result<ServingStatus, Failure> {
+    let headers = [
+        (":method", "POST"),
+        (":scheme", scheme),
+        (":path", HEALTH_CHECK_PATH),
+        (":authority", authority),
+        ("content-type", "application/grpc"),
+        ("te", "trailers"),
+        ("user-agent", concat!("griffin/", env!("CARGO_PKG_VERSION"))),
+    ];
+    let mut msg = PREFACE.to_vec();
+    msg.extend(frame(FRAME_SETTINGS, 0, 0, &[]));
+    msg.extend(frame(
+        FRAME_HEADERS,
+        FLAG_END_HEADERS,
+        STREAM_ID,
+        &encode_headers(&mut Encoder::new(), &headers),
+    ));
+    msg.extend(frame(
+        FRAME_DATA,
+        FLAG_END_STREAM,
+        STREAM_ID,
+        &grpc_message(&health_request(service)),
+    ));
+    stream.write_all(&msg)?;
+    stream.flush()?;
+
+    let (headers, body) = read_response(stream)?;
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k == name.as_bytes())
+            .map(|(_, v)| String::from_utf8_lossy(v).into_owned())
+    };
+    match header(":status") {
+        Some(status) if status == "200" => {}
+        Some(status) => {
+            return Err(match status.parse() {
+                Ok(status) => Failure::Status(status),
+                Err(_) => Failure::Protocol(format!("invalid :status {:?}", status)),
+            })
+        }
+        None => return Err(Failure::Protocol("response without :status".to_owned())),
+    }
+    match header("grpc-status").as_deref() {
+        Some("0") => {}
+        Some(code) => {
+            let name = code
+                .parse::<usize>()
+                .ok()
+                .and_then(|code| GRPC_CODES.get(code))
+                .map_or(code, |name| *name);
+            let message = header("grpc-message").map(|m| percent_decode(&m));
+            return Err(Failure::Grpc(match message {
+                Some(message) if !message.is_empty() => format!("status {}: {}", name, message),
+                _ => format!("status {}", name),
+            }));
+        }
+        None => return Err(Failure::Protocol("response without grpc-status".to_owned())),
+    }
+    serving_status(&body)
+}
+
+/// Reads frames until the request stream ends, answering the connection
+/// level frames that need it. Returns the headers and trailers along with the
+/// body of the stream.
+fn read_response<S: Read + Write>(stream: &mut S) -> Result<(Headers, Vec<u8>), Failure> {
+    let mut decoder = Decoder::new();
+    let mut headers = Vec::new();
+    let mut block = Vec::new();
+    let mut body = Vec::new();
+    let mut ended = false;
+    loop {
+        let frame = read_frame(stream)?;
+        match frame.kind {
+            FRAME_SETTINGS if frame.flags & FLAG_ACK == 0 => {
+                stream.write_all(&self::frame(FRAME_SETTINGS, FLAG_ACK, 0, &[]))?;
+            }
+            FRAME_PING if frame.flags & FLAG_ACK == 0 => {
+                stream.write_all(&self::frame(FRAME_PING, FLAG_ACK, 0, &frame.payload))?;
+            }
+            FRAME_GOAWAY => {
+                return Err(Failure::Protocol(format!(
+                    "connection closed by the host with error code {}",
+                    u32_at(&frame.payload, 4).unwrap_or_default()
+                )));
+            }
+            FRAME_RST_STREAM if frame.stream == STREAM_ID => {
+                return Err(Failure::Protocol(format!(
+                    "stream reset by the host with error code {}",
+                    u32_at(&frame.payload, 0).unwrap_or_default()
+                )));
+            }
+            FRAME_HEADERS | FRAME_CONTINUATION if frame.stream == STREAM_ID => {
+                if frame.kind == FRAME_HEADERS {
+                    block.extend_from_slice(unpad(&frame)?);
+                    ended = frame.flags & FLAG_END_STREAM != 0;
+                } else {
+                    block.extend_from_slice(&frame.payload);
+                }
+                if block.len() > MAX_HEADER_BLOCK_SIZE {
+                    return Err(Failure::Protocol("header block is too large".to_owned()));
+                }
+                if frame.flags & FLAG_END_HEADERS != 0 {
+                    let decoded = decoder
+                        .decode(&block)
+                        .map_err(|e| Failure::Protocol(format!("invalid header block: {:?}", e)))?;
+                    headers.extend(decoded);
+                    block.clear();
+                    if ended {
+                        return Ok((headers, body));
+                    }
+                }
+            }
+            FRAME_DATA if frame.stream == STREAM_ID => {
+                body.extend_from_slice(unpad(&frame)?);
+                if body.len() > MAX_BODY_SIZE {
+                    return Err(Failure::Protocol("response body is too large".to_owned()));
+                }
+                if frame.flags & FLAG_END_STREAM != 0 {
+                    return Ok((headers, body));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Header names and values
+type Headers = Vec<(Vec<u8>, Vec<u8>)>;
+
+fn encode_headers(encoder: &mut Encoder, headers: &[(&str, &str)]) -> Vec<u8> {
+    let headers: Headers = headers
+        .iter()
+        .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+        .collect();
+    encoder.encode(&headers)
+}
+
+/// Serializes a frame
+fn frame(kind: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend_from_slice(&stream.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn read_frame<R: Read>(rdr: &mut R) -> Result<Frame, Failure> {
+    let closed = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            Failure::Protocol("connection closed before the response ended".to_owned())
+        }
+        _ => e.into(),
+    };
+    let mut head = [0; 9];
+    rdr.read_exact(&mut head).map_err(closed)?;
+    let len = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(Failure::Protocol(format!(
+            "frame of {} bytes is too large",
+            len
+        )));
+    }
+    let mut payload = vec![0; len];
+    rdr.read_exact(&mut payload).map_err(closed)?;
+    Ok(Frame {
+        kind: head[3],
+        flags: head[4],
+        stream: u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff,
+        payload,
+    })
+}
+
+/// Payload of a DATA or HEADERS frame without padding and priority fields
+fn unpad(frame: &Frame) -> Result<&[u8], Failure> {
+    let invalid = || Failure::Protocol("invalid frame padding".to_owned());
+    let payload = &frame.payload[..];
+    let (mut start, mut end) = (0, payload.len());
+    if frame.flags & FLAG_PADDED != 0 {
+        let pad = *payload.first().ok_or_else(invalid)? as usize;
+        start = 1;
+        end = end.checked_sub(pad).ok_or_else(invalid)?;
+    }
+    if frame.kind == FRAME_HEADERS && frame.flags & FLAG_PRIORITY != 0 {
+        start += 5;
+    }
+    payload.get(start..end).ok_or_else(invalid)
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Option<u32> {
+    buf.get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Wraps a protobuf message in the length prefixed gRPC framing
+fn grpc_message(message: &[u8]) -> Vec<u8> {
+    let mut msg = vec![0];
+    msg.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    msg.extend_from_slice(message);
+    msg
+}
+
+/// Encodes a `HealthCheckRequest`, which has the service name as field 1
+fn health_request(service: &str) -> Vec<u8> {
+    let mut msg = Vec::new();
+    if !service.is_empty() {
+        msg.push(0x0a);
+        put_varint(&mut msg, service.len() as u64);
+        msg.extend_from_slice(service.as_bytes());
+    }
+    msg
+}
+
+/// Decodes the serving status, field 1, of a `HealthCheckResponse` out of a
+/// response body
+fn serving_status(body: &[u8]) -> Result<ServingStatus, Failure> {
+    let invalid =
+        |what: &str| Failure::Protocol(format!("invalid health check response: {}", what));
+    if body.first() != Some(&0) {
+        return Err(invalid("missing or compressed message"));
+    }
+    let len = u32_at(body, 1).ok_or_else(|| invalid("truncated message"))? as usize;
+    let msg = body
+        .get(5..5 + len)
+        .ok_or_else(|| invalid("truncated message"))?;
+
+    let mut status = 0;
+    let mut pos = 0;
+    while pos < msg.len() {
+        let key = read_varint(msg, &mut pos).ok_or_else(|| invalid("bad field"))?;
+        let skip = match (key >> 3, key & 0x7) {
+            (1, 0) => {
+                status = read_varint(msg, &mut pos).ok_or_else(|| invalid("bad status"))?;
+                0
+            }
+            (_, 0) => read_varint(msg, &mut pos)
+                .map(|_| 0)
+                .ok_or_else(|| invalid("bad field"))?,
+            (_, 1) => 8,
+            (_, 2) => read_varint(msg, &mut pos)
+                .and_then(|len| usize::try_from(len).ok())
+                .ok_or_else(|| invalid("bad field"))?,
+            (_, 5) => 4,
+            _ => return Err(invalid("unsupported wire type")),
+        };
+        // lengths come from the host, so a huge one must not wrap around
+        pos = pos
+            .checked_add(skip)
+            .filter(|&p| p <= msg.len())
+            .ok_or_else(|| invalid("truncated field"))?;
+    }
+    Ok(ServingStatus::from(status))
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::tls::testing::TestPki;
+    use rustls::{ServerConnection, StreamOwned};
+    use std::{net::TcpListener, sync::Arc, thread};
+
+    /// Answers a single health check request like a gRPC server would, using
+    /// the serving status of each known service
+    fn answer<S: Read + Write>(stream: &mut S, services: &[(&str, u64)]) -> Result<(), Failure> {
+        let mut preface = [0; 24];
+        stream.read_exact(&mut preface)?;
+        assert_eq!(&preface[..], PREFACE);
+        stream.write_all(&frame(FRAME_SETTINGS, 0, 0, &[]))?;
+
+        let mut decoder = Decoder::new();
+        let mut headers = Vec::new();
+        let mut body = Vec::new();
+        loop {
+            let frame = read_frame(stream)?;
+            match frame.kind {
+                FRAME_SETTINGS if frame.flags & FLAG_ACK == 0 => {
+                    stream.write_all(&self::frame(FRAME_SETTINGS, FLAG_ACK, 0, &[]))?;
+                }
+                FRAME_HEADERS => headers = decoder.decode(&frame.payload).unwrap(),
+                FRAME_DATA => {
+                    body.extend_from_slice(&frame.payload);
+                    if frame.flags & FLAG_END_STREAM != 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert!(headers.contains(&(b":path".to_vec(), HEALTH_CHECK_PATH.as_bytes().to_vec())));
+
+        // the request holds nothing but the service name
+        let service = String::from_utf8_lossy(body.get(7..).unwrap_or_default()).into_owned();
+        let mut encoder = Encoder::new();
+        let head = [(":status", "200"), ("content-type", "application/grpc")];
+        let mut msg = Vec::new();
+        match services.iter().find(|(name, _)| *name == service) {
+            Some((_, status)) => {
+                let block = encode_headers(&mut encoder, &head);
+                msg.extend(frame(FRAME_HEADERS, FLAG_END_HEADERS, STREAM_ID, &block));
+                let response = grpc_message(&[0x08, *status as u8]);
+                msg.extend(frame(FRAME_DATA, 0, STREAM_ID, &response));
+                let trailers = encode_headers(&mut encoder, &[("grpc-status", "0")]);
+                let flags = FLAG_END_HEADERS | FLAG_END_STREAM;
+                msg.extend(frame(FRAME_HEADERS, flags, STREAM_ID, &trailers));
+            }
+            None => {
+                let mut trailers = head.to_vec();
+                trailers.push(("grpc-status", "5"));
+                trailers.push(("grpc-message", "unknown%20service"));
+                // split over a CONTINUATION frame like big header blocks are
+                let block = encode_headers(&mut encoder, &trailers);
+                let (first, rest) = block.split_at(block.len() / 2);
+                msg.extend(frame(FRAME_HEADERS, FLAG_END_STREAM, STREAM_ID, first));
+                msg.extend(frame(FRAME_CONTINUATION, FLAG_END_HEADERS, STREAM_ID, rest));
+            }
+        }
+        stream.write_all(&msg)?;
+        stream.flush()?;
+        // closing with unread frames like a SETTINGS ack would reset the
+        // connection, so wait for the client to hang up first
+        let _ = io::copy(stream, &mut io::sink());
+        Ok(())
+    }
+
+    /// Stream that plays back canned frames and ignores what is written
+    struct Canned(io::Cursor<Vec<u8>>);
+
+    impl Read for Canned {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for Canned {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn call_canned(frames: Vec<u8>) -> Result<ServingStatus, Failure> {
+        call(&mut Canned(io::Cursor::new(frames)), "http", "x", "")
+    }
+
+    #[test]
+    fn rejects_bad_responses() {
+        let mut encoder = Encoder::new();
+        let block = encode_headers(&mut encoder, &[(":status", "abc")]);
+        let flags = FLAG_END_HEADERS | FLAG_END_STREAM;
+        assert_eq!(
+            call_canned(frame(FRAME_HEADERS, flags, STREAM_ID, &block)),
+            Err(Failure::Protocol("invalid :status \"abc\"".to_owned()))
+        );
+
+        // a header block that never ends
+        let mut frames = frame(FRAME_HEADERS, 0, STREAM_ID, &[0; MAX_FRAME_SIZE]);
+        for _ in 0..MAX_HEADER_BLOCK_SIZE / MAX_FRAME_SIZE {
+            frames.extend(frame(
+                FRAME_CONTINUATION,
+                0,
+                STREAM_ID,
+                &[0; MAX_FRAME_SIZE],
+            ));
+        }
+        assert_eq!(
+            call_canned(frames),
+            Err(Failure::Protocol("header block is too large".to_owned()))
+        );
+    }
+
+    const SERVICES: [(&str, u64); 3] = [("", 1), ("orders", 2), ("payments", 0)];
+
+    /// Serves the health service over cleartext HTTP/2
+    fn serve_h2c() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = answer(&mut stream.unwrap(), &SERVICES);
+            }
+        });
+        port
+    }
+
+    fn grpc_check(port: u16, extra: &str) -> HealthCheck {
+        let check = format!("{{method: grpc, port: {}{}}}", port, extra);
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn maps_serving_status() {
+        let port = serve_h2c();
+        let result = check("127.0.0.1", &grpc_check(port, ""));
+        assert_eq!(result.status, Status::Ok, "{:?}", result.failure);
+
+        let result = check("127.0.0.1", &grpc_check(port, ", grpc_service: orders"));
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(
+            result.failure,
+            Some(Failure::Grpc("service is NOT_SERVING".to_owned()))
+        );
+
+        let result = check("127.0.0.1", &grpc_check(port, ", grpc_service: payments"));
+        assert_eq!(result.status, Status::Unknown);
+    }
+
+    #[test]
+    fn fails_on_unknown_service() {
+        let port = serve_h2c();
+        let result = check("127.0.0.1", &grpc_check(port, ", grpc_service: missing"));
+        assert_eq!(
+            result.failure,
+            Some(Failure::Grpc(
+                "status NOT_FOUND: unknown service".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn checks_over_tls() {
+        let pki = TestPki::new();
+        let mut config = pki.server_config(false);
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let config = Arc::new(config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for sock in listener.incoming() {
+                let conn = ServerConnection::new(Arc::clone(&config)).unwrap();
+                let mut stream = StreamOwned::new(conn, sock.unwrap());
+                let _ = answer(&mut stream, &SERVICES);
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
+            }
+        });
+
+        let extra = format!(", scheme: https, tls: {{ca_file: {:?}}}", pki.ca_file);
+        let result = check("localhost", &grpc_check(port, &extra));
+        assert_eq!(result.status, Status::Ok, "{:?}", result.failure);
+    }
+
+    #[test]
+    fn decode_health_messages() {
+        assert_eq!(health_request("db"), vec![0x0a, 2, b'd', b'b']);
+        assert_eq!(
+            serving_status(&grpc_message(&[0x08, 0x01])),
+            Ok(ServingStatus::Serving)
+        );
+        // proto3 leaves out fields with default values
+        assert_eq!(
+            serving_status(&grpc_message(&[])),
+            Ok(ServingStatus::Unknown)
+        );
+        // unknown fields are skipped
+        assert_eq!(
+            serving_status(&grpc_message(&[0x12, 1, b'x', 0x08, 0x02])),
+            Ok(ServingStatus::NotServing)
+        );
+        assert!(serving_status(&[0, 0, 0, 0, 9, 0x08]).is_err());
+        // length-delimited fields longer than the message
+        let truncated = Err(Failure::Protocol(
+            "invalid health check response: truncated field".to_owned(),
+        ));
+        assert_eq!(
+            serving_status(&grpc_message(&[0x12, 5, b'x', 0x08, 0x01])),
+            truncated
+        );
+        let huge = [
+            0x12, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ];
+        assert_eq!(serving_status(&grpc_message(&huge)), truncated);
+        assert_eq!(percent_decode("no%20such%2Fservice%"), "no such/service%");
+    }
+}
@@ -0,0 +1,740 @@
+use std::{
+    fmt::{self, Display},
+    io::{BufRead, BufReader, Read, Write},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use super::{connect, timeout, tls::connect_tls, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::{HealthCheck, HttpExpect, Scheme, Secret, TlsOptions};
+
+/// Port used when a http check does not set one
+pub const DEFAULT_PORT: u16 = 80;
+
+/// Port used when a https check does not set one
+pub const DEFAULT_TLS_PORT: u16 = 443;
+
+/// Largest response body read from a host
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Longest status line and headers accepted from a host
+const MAX_HEAD_SIZE: u64 = 64 * 1024;
+
+/// HTTP header names and values, in order
+pub type Headers = Vec<(String, String)>;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Error when an url is not valid
+pub struct UrlError {
+    url: String,
+}
+
+impl Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid url {}", self.url)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Parts of a http or https url
+pub struct Url {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl FromStr for Url {
+    type Err = UrlError;
+
+    /// Splits an url like `https://example.com:8443/hook` into its parts
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || UrlError { url: s.to_owned() };
+        let (scheme, rest) = s.split_once("://").ok_or_else(err)?;
+        let scheme = scheme.to_lowercase();
+        let default_port = match scheme.as_str() {
+            "http" => DEFAULT_PORT,
+            "https" => DEFAULT_TLS_PORT,
+            _ => return Err(err()),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => (
+                &authority[..i],
+                authority[i + 1..].parse::<u16>().map_err(|_| err())?,
+            ),
+            _ => (authority, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(err());
+        }
+        Ok(Self {
+            scheme,
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A parsed HTTP response
+pub struct Response {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Gets the value of a header, ignoring the case of its name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Probes `endpoint` of a host over HTTP
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let tls = match check.scheme {
+        Scheme::Http => None,
+        Scheme::Https => Some(&check.tls),
+    };
+    let default_port = if tls.is_some() {
+        DEFAULT_TLS_PORT
+    } else {
+        DEFAULT_PORT
+    };
+
+    let response = request_parts(check).and_then(|(headers, body)| {
+        let headers: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let method = check.http_method.to_string();
+        Request {
+            method: &method,
+            host,
+            port: check.port.unwrap_or(default_port),
+            path: check.endpoint.as_deref().unwrap_or("/"),
+            headers: &headers,
+            body: &body,
+            tls,
+        }
+        .send(timeout(check, DEFAULT_TIMEOUT))
+    });
+    match response {
+        Ok(response) => {
+            let latency = start.elapsed();
+            let mut result = match verify(&response, latency, &check.expect) {
+                Ok(()) => CheckResult::success(latency),
+                Err(failure) => CheckResult::failure(latency, failure),
+            };
+            result.status_code = Some(response.status);
+            result
+        }
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+/// Resolves the headers and body a check sends
+pub(crate) fn request_parts(check: &HealthCheck) -> Result<(Headers, Vec<u8>), Failure> {
+    let mut headers = Vec::new();
+    for (name, value) in &check.headers {
+        headers.push((name.clone(), header_value(name, value)?));
+    }
+    if let Some(auth) = &check.basic_auth {
+        let credentials = format!(
+            "{}:{}",
+            header_value("basic_auth.username", &auth.username)?,
+            header_value("basic_auth.password", &auth.password)?
+        );
+        headers.push((
+            "Authorization".to_owned(),
+            format!("Basic {}", base64::encode(credentials)),
+        ));
+    }
+    if let Some(token) = &check.bearer_token {
+        headers.push((
+            "Authorization".to_owned(),
+            format!("Bearer {}", header_value("bearer_token", token)?),
+        ));
+    }
+    let body = match &check.body {
+        Some(body) => body.resolve()?.into_bytes(),
+        None => Vec::new(),
+    };
+    Ok((headers, body))
+}
+
+/// Resolves a secret that goes into a header. A line break in it would end
+/// the header and start another one.
+fn header_value(name: &str, secret: &Secret) -> Result<String, Failure> {
+    let value = secret.resolve()?;
+    if value.contains(&['\r', '\n'][..]) {
+        return Err(Failure::Config(format!("{} holds a line break", name)));
+    }
+    Ok(value)
+}
+
+/// Checks a response against the expectations of a check
+fn verify(response: &Response, latency: Duration, expect: &HttpExpect) -> Result<(), Failure> {
+    let status_ok = if expect.status.is_empty() {
+        (200..300).contains(&response.status)
+    } else {
+        expect.status.iter().any(|r| r.contains(response.status))
+    };
+    if !status_ok {
+        return Err(Failure::Status(response.status));
+    }
+
+    if let Some(max) = &expect.max_response_time {
+        if latency > max.as_duration() {
+            return Err(Failure::Assertion(format!(
+                "response took {}ms, more than {}",
+                latency.as_millis(),
+                max
+            )));
+        }
+    }
+
+    for (name, expected) in &expect.headers {
+        match (response.header(name), expected) {
+            (None, _) => {
+                return Err(Failure::Assertion(format!("header {} is missing", name)));
+            }
+            (Some(value), Some(expected)) if !value.contains(expected.as_str()) => {
+                return Err(Failure::Assertion(format!(
+                    "header {} is {:?}, expected it to contain {:?}",
+                    name, value, expected
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    if expect.body.is_some() || expect.body_regex.is_some() {
+        let body = String::from_utf8_lossy(&response.body);
+        if let Some(text) = &expect.body {
+            if !body.contains(text.as_str()) {
+                return Err(Failure::Assertion(format!(
+                    "body does not contain {:?}",
+                    text
+                )));
+            }
+        }
+        if let Some(re) = &expect.body_regex {
+            if !re.is_match(&body) {
+                return Err(Failure::Assertion(format!(
+                    "body does not match /{}/",
+                    re.as_str()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A HTTP request to send
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub host: &'a str,
+    pub port: u16,
+    pub path: &'a str,
+    /// Extra headers, replacing default headers of the same name
+    pub headers: &'a [(&'a str, &'a str)],
+    pub body: &'a [u8],
+    /// Sends the request over TLS when set
+    pub tls: Option<&'a TlsOptions>,
+}
+
+impl<'a> Request<'a> {
+    /// Creates a plain HTTP GET request
+    pub fn get(host: &'a str, port: u16, path: &'a str) -> Self {
+        Self {
+            method: "GET",
+            host,
+            port,
+            path,
+            headers: &[],
+            body: &[],
+            tls: None,
+        }
+    }
+
+    /// Sends the request and reads the whole response
+    pub fn send(&self, timeout: Duration) -> Result<Response, Failure> {
+        let mut stream: Box<dyn ReadWrite> = match self.tls {
+            Some(opts) => Box::new(connect_tls(self.host, self.port, opts, timeout)?),
+            None => Box::new(connect(self.host, self.port, timeout)?),
+        };
+        stream.write_all(&self.head())?;
+        stream.write_all(self.body)?;
+        stream.flush()?;
+        read_response(BufReader::new(stream), self.method != "HEAD")
+    }
+
+    /// Request line and headers
+    fn head(&self) -> Vec<u8> {
+        let default_port = if self.tls.is_some() {
+            DEFAULT_TLS_PORT
+        } else {
+            DEFAULT_PORT
+        };
+        let host_header = authority(self.host, self.port, default_port);
+        let user_agent = format!("griffin/{}", env!("CARGO_PKG_VERSION"));
+        let defaults = [
+            ("Host", host_header.as_str()),
+            ("User-Agent", user_agent.as_str()),
+            ("Accept", "*/*"),
+        ];
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method, self.path);
+        push_headers(&mut head, &defaults, self.headers);
+        head.push_str("Connection: close\r\n");
+        if !self.body.is_empty() {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+}
+
+/// Adds the header lines of a request to its head, leaving out the defaults
+/// the headers of the check override
+pub(crate) fn push_headers<N, V>(head: &mut String, defaults: &[(&str, &str)], headers: &[(N, V)])
+where
+    N: AsRef<str>,
+    V: AsRef<str>,
+{
+    for (name, value) in defaults {
+        if !headers
+            .iter()
+            .any(|(n, _)| n.as_ref().eq_ignore_ascii_case(name))
+        {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name.as_ref(), value.as_ref()));
+    }
+}
+
+/// A stream a request can be sent over
+pub(crate) trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
+/// Reads a HTTP/1.x response from a reader, with its body if it may have one
+pub(crate) fn read_response<R: BufRead>(mut rdr: R, with_body: bool) -> Result<Response, Failure> {
+    let mut head = (&mut rdr).take(MAX_HEAD_SIZE);
+    let mut read_head_line = || match read_line(&mut head) {
+        Err(_) if head.limit() == 0 => Err(Failure::Protocol(
+            "response headers are too long".to_owned(),
+        )),
+        line => line,
+    };
+    let status_line = read_head_line()?;
+    let mut parts = status_line.splitn(3, ' ');
+    match parts.next() {
+        Some(version) if version.starts_with("HTTP/1.") => {}
+        _ => {
+            return Err(Failure::Protocol(format!(
+                "bad status line {:?}",
+                status_line
+            )))
+        }
+    }
+    let status = parts
+        .next()
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| Failure::Protocol(format!("bad status line {:?}", status_line)))?;
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_head_line()?;
+        if line.is_empty() {
+            break;
+        }
+        match line.split_once(':') {
+            Some((name, value)) => headers.push((name.trim().to_owned(), value.trim().to_owned())),
+            None => return Err(Failure::Protocol(format!("bad header {:?}", line))),
+        }
+    }
+
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let chunked = response
+        .header("transfer-encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+    let length = response
+        .header("content-length")
+        .and_then(|v| v.parse::<u64>().ok());
+
+    response.body = if !with_body || status == 204 || status == 304 {
+        Vec::new()
+    } else if chunked {
+        read_chunked(&mut rdr)?
+    } else {
+        let mut body = Vec::new();
+        let limit = length.unwrap_or(MAX_BODY_SIZE).min(MAX_BODY_SIZE);
+        rdr.take(limit).read_to_end(&mut body)?;
+        body
+    };
+    Ok(response)
+}
+
+/// Reads a body sent with chunked transfer encoding
+fn read_chunked<R: BufRead>(rdr: &mut R) -> Result<Vec<u8>, Failure> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(rdr)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| Failure::Protocol(format!("bad chunk size {:?}", line)))?;
+        if size == 0 {
+            break;
+        }
+        if body.len() as u64 + size > MAX_BODY_SIZE {
+            return Err(Failure::Protocol("response body too large".to_owned()));
+        }
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        rdr.read_exact(&mut body[start..])?;
+        read_line(rdr)?;
+    }
+    Ok(body)
+}
+
+/// Host and port as sent in a Host header or :authority, leaving out the
+/// default port and putting IPv6 addresses in brackets
+pub(crate) fn authority(host: &str, port: u16, default_port: u16) -> String {
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_owned()
+    };
+    if port == default_port {
+        host
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Reads a single CRLF terminated line, no longer than a response head
+fn read_line<R: BufRead>(rdr: &mut R) -> Result<String, Failure> {
+    let mut line = String::new();
+    (&mut *rdr).take(MAX_HEAD_SIZE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(Failure::Protocol(if line.len() as u64 == MAX_HEAD_SIZE {
+            "line is too long".to_owned()
+        } else {
+            "unexpected end of response".to_owned()
+        }));
+    }
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::tls::testing::TestPki;
+    use std::{net::TcpListener, sync::mpsc, thread};
+
+    /// Serves a single canned response on a random local port
+    fn serve_once(response: &'static str) -> u16 {
+        serve_recording(response).0
+    }
+
+    /// Serves a single canned response and passes on the request it got
+    fn serve_recording(response: &'static str) -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut rdr = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                rdr.read_line(&mut line).unwrap();
+                if let Some(v) = line.strip_prefix("Content-Length: ") {
+                    length = v.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            rdr.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            rdr.get_mut().write_all(response.as_bytes()).unwrap();
+            let _ = tx.send(request);
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn brackets_ipv6_hosts() {
+        assert_eq!(authority("example.com", 80, 80), "example.com");
+        assert_eq!(authority("example.com", 8080, 80), "example.com:8080");
+        assert_eq!(authority("::1", 8080, 80), "[::1]:8080");
+        assert_eq!(authority("::1", 443, 443), "[::1]");
+
+        let url: Url = "http://[::1]:9000/hook".parse().unwrap();
+        let head = Request::get(&url.host, url.port, &url.path).head();
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.contains("\r\nHost: [::1]:9000\r\n"), "{}", head);
+    }
+
+    fn http_check(port: u16) -> HealthCheck {
+        let check = format!("{{method: http, endpoint: /status, port: {}}}", port);
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> Response {
+        Response {
+            status,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn successful_http_check() {
+        let port = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let result = check("127.0.0.1", &http_check(port));
+        assert!(result.is_success());
+        assert_eq!(result.status_code, Some(200));
+    }
+
+    #[test]
+    fn failed_http_check_on_server_error() {
+        let port = serve_once("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+        let result = check("127.0.0.1", &http_check(port));
+        assert_eq!(result.status_code, Some(503));
+        assert_eq!(result.failure, Some(Failure::Status(503)));
+    }
+
+    #[test]
+    fn sends_configured_request() {
+        std::env::set_var("GRIFFIN_TEST_API_TOKEN", "t0ken");
+        let (port, rx) = serve_recording("HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n");
+        let check = format!(
+            r###"
+            method: http
+            endpoint: /api/health
+            port: {}
+            http_method: POST
+            headers:
+              Content-Type: application/json
+              User-Agent: probe
+            body: '{{"deep": true}}'
+            bearer_token: ${{GRIFFIN_TEST_API_TOKEN}}
+            "###,
+            port
+        );
+        let check: HealthCheck = serde_yaml::from_str(&check).unwrap();
+        assert!(super::check("127.0.0.1", &check).is_success());
+
+        let request = rx.recv().unwrap();
+        assert!(request.starts_with("POST /api/health HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nContent-Type: application/json\r\n"));
+        assert!(request.contains("\r\nAuthorization: Bearer t0ken\r\n"));
+        assert!(request.contains("\r\nUser-Agent: probe\r\n"));
+        assert!(!request.contains("griffin/"));
+        assert!(request.ends_with("\r\n\r\n{\"deep\": true}"));
+    }
+
+    #[test]
+    fn basic_auth_header() {
+        let check: HealthCheck = serde_yaml::from_str(
+            "{method: http, basic_auth: {username: Aladdin, password: open sesame}}",
+        )
+        .unwrap();
+        let (headers, body) = request_parts(&check).unwrap();
+        assert_eq!(
+            headers,
+            vec![(
+                "Authorization".to_owned(),
+                "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==".to_owned()
+            )]
+        );
+        assert!(body.is_empty());
+
+        let check: HealthCheck =
+            serde_yaml::from_str("{method: http, bearer_token: '${GRIFFIN_TEST_UNSET}'}").unwrap();
+        assert!(matches!(request_parts(&check), Err(Failure::Secret(_))));
+
+        // a line break in a secret would smuggle in another header
+        std::env::set_var("GRIFFIN_TEST_SPLIT_TOKEN", "t0ken\r\nX-Admin: 1");
+        let check: HealthCheck =
+            serde_yaml::from_str("{method: http, bearer_token: '${GRIFFIN_TEST_SPLIT_TOKEN}'}")
+                .unwrap();
+        assert_eq!(
+            request_parts(&check),
+            Err(Failure::Config(
+                "bearer_token holds a line break".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn successful_https_check() {
+        let pki = TestPki::new();
+        let port = pki.serve("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok", false);
+        let check = format!(
+            "{{method: http, scheme: https, endpoint: /status, port: {}, tls: {{ca_file: {:?}}}}}",
+            port, pki.ca_file
+        );
+        let check: HealthCheck = serde_yaml::from_str(&check).unwrap();
+        let result = super::check("localhost", &check);
+        assert!(result.is_success(), "{:?}", result.failure);
+        assert_eq!(result.status_code, Some(200));
+
+        // without the CA the certificate can't be trusted
+        let check = format!(
+            "{{method: http, scheme: https, endpoint: /status, port: {}}}",
+            port
+        );
+        let check: HealthCheck = serde_yaml::from_str(&check).unwrap();
+        let result = super::check("localhost", &check);
+        assert!(matches!(result.failure, Some(Failure::Tls(_))));
+    }
+
+    #[test]
+    fn failed_http_check_on_refused_connection() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let result = check("127.0.0.1", &http_check(port));
+        assert!(matches!(result.failure, Some(Failure::Connect(_))));
+    }
+
+    #[test]
+    fn verify_expectations() {
+        let expect: HttpExpect = serde_yaml::from_str(
+            r###"
+            status: [200, 301]
+            body: healthy
+            body_regex: "version \\d+"
+            headers:
+              Content-Type: json
+              X-Served-By:
+            max_response_time: 100ms
+            "###,
+        )
+        .unwrap();
+        let headers = [("content-type", "application/json"), ("X-Served-By", "a")];
+        let fast = Duration::from_millis(5);
+        let ok = response(301, &headers, "healthy, version 3");
+        assert_eq!(verify(&ok, fast, &expect), Ok(()));
+
+        let failure = |response: &Response, latency| match verify(response, latency, &expect) {
+            Err(Failure::Assertion(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(
+            verify(&response(200, &[], ""), fast, &HttpExpect::default()),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&response(404, &headers, ""), fast, &expect),
+            Err(Failure::Status(404))
+        );
+        assert_eq!(
+            failure(&ok, Duration::from_millis(250)),
+            "response took 250ms, more than 100ms"
+        );
+        assert_eq!(
+            failure(&response(200, &headers[..1], "healthy"), fast),
+            "header X-Served-By is missing"
+        );
+        assert_eq!(
+            failure(
+                &response(200, &[("Content-Type", "text/html"), headers[1]], ""),
+                fast
+            ),
+            "header Content-Type is \"text/html\", expected it to contain \"json\""
+        );
+        assert_eq!(
+            failure(&response(200, &headers, "sick"), fast),
+            "body does not contain \"healthy\""
+        );
+        assert_eq!(
+            failure(&response(200, &headers, "healthy"), fast),
+            "body does not match /version \\d+/"
+        );
+    }
+
+    #[test]
+    fn parse_url() {
+        let url: Url = "https://example.com/abcd".parse().unwrap();
+        assert_eq!(url.scheme, "https");
+        assert_eq!((url.host.as_str(), url.port), ("example.com", 443));
+        assert_eq!(url.path, "/abcd");
+
+        let url: Url = "http://[::1]:8080".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 8080));
+        assert_eq!(url.path, "/");
+
+        assert!("ftp://example.com".parse::<Url>().is_err());
+        assert!("example.com".parse::<Url>().is_err());
+    }
+
+    #[test]
+    fn read_chunked_response() {
+        let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+        let response = read_response(raw.as_bytes(), true).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"Wikipedia");
+    }
+
+    #[test]
+    fn limits_response_head() {
+        let endless = format!("HTTP/1.1 200 OK\r\nX-Long: {}", "a".repeat(100_000));
+        assert_eq!(
+            read_response(endless.as_bytes(), true).err(),
+            Some(Failure::Protocol(
+                "response headers are too long".to_owned()
+            ))
+        );
+        let many = format!("HTTP/1.1 200 OK\r\n{}\r\n", "X-A: b\r\n".repeat(10_000));
+        assert_eq!(
+            read_response(many.as_bytes(), true).err(),
+            Some(Failure::Protocol(
+                "response headers are too long".to_owned()
+            ))
+        );
+        let chunk = format!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{}",
+            "0".repeat(100_000)
+        );
+        assert_eq!(
+            read_response(chunk.as_bytes(), true).err(),
+            Some(Failure::Protocol("line is too long".to_owned()))
+        );
+        assert_eq!(
+            read_response("HTTP/1.1 200 OK\r\nX-A: b".as_bytes(), true).err(),
+            Some(Failure::Protocol("unexpected end of response".to_owned()))
+        );
+    }
+}
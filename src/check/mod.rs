@@ -0,0 +1,402 @@
+use std::{
+    fmt::{self, Display},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{HealthCheck, HealthCheckMethod, SecretError, Service};
+use exec::PerfData;
+
+pub mod cert;
+pub mod dns;
+pub mod exec;
+pub mod grpc;
+pub mod http;
+pub mod mysql;
+pub mod ping;
+pub mod postgres;
+pub mod redis;
+pub mod tcp;
+pub mod tls;
+pub mod udp;
+pub mod websocket;
+
+/// Time allowed for a single probe before it is considered failed
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+/// Reason a health check failed
+pub enum Failure {
+    /// Host name could not be resolved
+    Resolve(String),
+    /// Connection to the host could not be established
+    Connect(String),
+    /// Probe did not complete within the timeout
+    Timeout,
+    /// Connection broke while talking to the host
+    Io(String),
+    /// Host answered with something we could not understand
+    Protocol(String),
+    /// HTTP response had an unexpected status code
+    Status(u16),
+    /// Some probes got no answer
+    PacketLoss(f32),
+    /// Response did not meet an expectation of the check
+    Assertion(String),
+    /// A secret used by the check could not be resolved
+    Secret(SecretError),
+    /// TLS handshake failed or the certificate was not accepted
+    Tls(String),
+    /// Certificate is expiring, not valid for the host or weakly signed
+    Certificate(String),
+    /// Command of an exec check could not run or reported a problem
+    Command(String),
+    /// gRPC call failed or the service is not serving
+    Grpc(String),
+    /// Host refused the credentials of the check
+    Auth(String),
+    /// Database reported an error for the query of the check
+    Database(String),
+    /// A value of the check can't be used the way it is set
+    Config(String),
+    /// Failure of a result read back from the history, kept as its text
+    Recorded(String),
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Resolve(e) => write!(f, "could not resolve host: {}", e),
+            Failure::Connect(e) => write!(f, "could not connect: {}", e),
+            Failure::Timeout => write!(f, "timed out"),
+            Failure::Io(e) => write!(f, "connection error: {}", e),
+            Failure::Protocol(e) => write!(f, "invalid response: {}", e),
+            Failure::Status(code) => write!(f, "unexpected status code {}", code),
+            Failure::PacketLoss(loss) => write!(f, "{:.0}% packet loss", loss),
+            Failure::Assertion(e) => write!(f, "assertion failed: {}", e),
+            Failure::Secret(e) => write!(f, "missing secret: {}", e),
+            Failure::Tls(e) => write!(f, "TLS error: {}", e),
+            Failure::Certificate(e) => write!(f, "certificate {}", e),
+            Failure::Command(e) => f.write_str(e),
+            Failure::Grpc(e) => write!(f, "gRPC {}", e),
+            Failure::Auth(e) => write!(f, "authentication failed: {}", e),
+            Failure::Database(e) => write!(f, "database error: {}", e),
+            Failure::Config(e) => write!(f, "invalid config: {}", e),
+            Failure::Recorded(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<SecretError> for Failure {
+    fn from(e: SecretError) -> Self {
+        Failure::Secret(e)
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Failure::Timeout,
+            _ => Failure::Io(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// How healthy a check found its host, following the Nagios plugin states
+pub enum Status {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl Status {
+    /// Exit code a Nagios plugin uses for this status
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+
+    /// Ranks how bad a status is, critical being the worst
+    fn rank(self) -> u8 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Unknown => 2,
+            Status::Critical => 3,
+        }
+    }
+
+    /// The worse of two statuses
+    pub fn worst(self, other: Status) -> Status {
+        if other.rank() > self.rank() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "OK"),
+            Status::Warning => write!(f, "WARNING"),
+            Status::Critical => write!(f, "CRITICAL"),
+            Status::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Outcome of running a single health check once
+pub struct CheckResult {
+    pub status: Status,
+    pub latency: Duration,
+    pub status_code: Option<u16>,
+    /// Percentage of probes that got no answer
+    pub packet_loss: Option<f32>,
+    /// When the first certificate of the chain expires
+    pub cert_expiry: Option<SystemTime>,
+    /// First line of output of an exec check
+    pub output: Option<String>,
+    pub perfdata: Vec<PerfData>,
+    /// Runs it took to get this result, more than one when retried
+    pub attempts: u32,
+    pub failure: Option<Failure>,
+}
+
+impl CheckResult {
+    /// Creates a successful result
+    pub fn success(latency: Duration) -> Self {
+        Self {
+            status: Status::Ok,
+            latency,
+            status_code: None,
+            packet_loss: None,
+            cert_expiry: None,
+            output: None,
+            perfdata: Vec::new(),
+            attempts: 1,
+            failure: None,
+        }
+    }
+
+    /// Creates a critical result
+    pub fn failure(latency: Duration, failure: Failure) -> Self {
+        Self::with_status(Status::Critical, latency, failure)
+    }
+
+    /// Creates a result that passed but found something worth a warning
+    pub fn warning(latency: Duration, failure: Failure) -> Self {
+        Self::with_status(Status::Warning, latency, failure)
+    }
+
+    /// Creates a result with a status and the reason for it
+    pub fn with_status(status: Status, latency: Duration, failure: Failure) -> Self {
+        Self {
+            status,
+            latency,
+            status_code: None,
+            packet_loss: None,
+            cert_expiry: None,
+            output: None,
+            perfdata: Vec::new(),
+            attempts: 1,
+            failure: Some(failure),
+        }
+    }
+
+    /// Whether the check passed, possibly with a warning
+    pub fn is_success(&self) -> bool {
+        matches!(self.status, Status::Ok | Status::Warning)
+    }
+}
+
+/// Runs a health check of a service, retrying failed runs as often as the
+/// check allows. Returns the result of the last attempt.
+pub fn run(service: &Service, check: &HealthCheck) -> CheckResult {
+    let mut result = run_once(service, check);
+    while !result.is_success() && result.attempts <= check.retries {
+        thread::sleep(check.retry_interval.as_duration());
+        let attempts = result.attempts + 1;
+        result = run_once(service, check);
+        result.attempts = attempts;
+    }
+    result
+}
+
+/// Runs a health check of a service once
+fn run_once(service: &Service, check: &HealthCheck) -> CheckResult {
+    match check.method {
+        HealthCheckMethod::Http => http::check(&service.host, check),
+        HealthCheckMethod::Ping => ping::check(&service.host, check),
+        HealthCheckMethod::TlsCert => cert::check(&service.host, check),
+        HealthCheckMethod::Tcp => tcp::check(&service.host, check),
+        HealthCheckMethod::Dns => dns::check(&service.host, check),
+        HealthCheckMethod::Udp => udp::check(&service.host, check),
+        HealthCheckMethod::Exec => exec::check(&service.host, check),
+        HealthCheckMethod::Grpc => grpc::check(&service.host, check),
+        HealthCheckMethod::Postgres => postgres::check(&service.host, check),
+        HealthCheckMethod::Mysql => mysql::check(&service.host, check),
+        HealthCheckMethod::Redis => redis::check(&service.host, check),
+        HealthCheckMethod::WebSocket => websocket::check(&service.host, check),
+    }
+}
+
+/// Decodes hex digits into bytes, ignoring whitespace between them
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let err = || format!("{:?} is not valid hex", hex);
+    if digits.len() % 2 != 0 {
+        return Err(err());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(err)
+        })
+        .collect()
+}
+
+/// Decodes the `%XX` escapes of a URL path or grpc-message, leaving
+/// malformed ones as they are
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Time each probe of a check may take, `default` unless the check sets one
+pub(crate) fn timeout(check: &HealthCheck, default: Duration) -> Duration {
+    check.timeout.as_ref().map_or(default, |t| t.as_duration())
+}
+
+/// Data a tcp or udp check sends, either as text or as hex
+pub(crate) fn payload(check: &HealthCheck) -> Result<Vec<u8>, Failure> {
+    match (&check.payload, &check.payload_hex) {
+        (Some(text), _) => Ok(text.as_bytes().to_vec()),
+        (None, Some(hex)) => decode_hex(hex).map_err(Failure::Assertion),
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+/// Resolves a host and port to socket addresses
+pub(crate) fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, Failure> {
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| Failure::Resolve(e.to_string()))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(Failure::Resolve(format!("no addresses found for {}", host)));
+    }
+    Ok(addrs)
+}
+
+/// Opens a TCP connection to the first reachable address of a host
+pub(crate) fn connect(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, Failure> {
+    let mut last = Failure::Connect(format!("{}:{} unreachable", host, port));
+    for addr in resolve(host, port)? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => last = Failure::Timeout,
+            Err(e) => last = Failure::Connect(e.to_string()),
+        }
+    }
+    Err(last)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{env, fs, process};
+
+    /// A service with an exec check that fails until it has run `passes_on`
+    /// times, counting runs in a file
+    fn flaky_service(passes_on: u32, retries: u32) -> (Service, std::path::PathBuf) {
+        let counter = env::temp_dir().join(format!(
+            "griffin-flaky-{}-{}-{}",
+            process::id(),
+            passes_on,
+            retries
+        ));
+        let _ = fs::remove_file(&counter);
+        let script = format!(
+            "n=$(($(cat {0} 2>/dev/null || echo 0) + 1)); echo $n > {0}; [ $n -ge {1} ] || exit 2",
+            counter.display(),
+            passes_on
+        );
+        let service = format!(
+            r###"
+            name: Flaky
+            host: localhost
+            health:
+              - method: exec
+                command: sh
+                args: [-c, {:?}]
+                retries: {}
+                retry_interval: 10ms
+            "###,
+            script, retries
+        );
+        (serde_yaml::from_str(&service).unwrap(), counter)
+    }
+
+    #[test]
+    fn retries_failed_runs() {
+        let (service, counter) = flaky_service(3, 2);
+        let result = run(&service, &service.health[0]);
+        assert_eq!(result.status, Status::Ok);
+        assert_eq!(result.attempts, 3);
+        fs::remove_file(counter).unwrap();
+
+        let (service, counter) = flaky_service(3, 1);
+        let result = run(&service, &service.health[0]);
+        assert_eq!(result.status, Status::Critical);
+        assert_eq!(result.attempts, 2);
+        fs::remove_file(counter).unwrap();
+    }
+
+    #[test]
+    fn checks_use_their_timeout() {
+        let check: HealthCheck = serde_yaml::from_str("{method: ping, timeout: 3s}").unwrap();
+        assert_eq!(timeout(&check, DEFAULT_TIMEOUT), Duration::from_secs(3));
+        let check: HealthCheck = serde_yaml::from_str("{method: ping}").unwrap();
+        assert_eq!(timeout(&check, DEFAULT_TIMEOUT), DEFAULT_TIMEOUT);
+    }
+}
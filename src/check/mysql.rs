@@ -0,0 +1,406 @@
+use std::{
+    io::{Read, Write},
+    time::Instant,
+};
+
+use ring::digest;
+
+use super::{connect, timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// Port mysql listens on unless the check sets one
+const DEFAULT_PORT: u16 = 3306;
+
+/// Query run once logged in
+const QUERY: &str = "SELECT 1";
+
+const CLIENT_LONG_PASSWORD: u32 = 0x1;
+const CLIENT_CONNECT_WITH_DB: u32 = 0x8;
+const CLIENT_PROTOCOL_41: u32 = 0x200;
+const CLIENT_TRANSACTIONS: u32 = 0x2000;
+const CLIENT_SECURE_CONNECTION: u32 = 0x8000;
+const CLIENT_PLUGIN_AUTH: u32 = 0x8_0000;
+
+/// utf8mb4_general_ci
+const CHARSET: u8 = 45;
+
+const COM_QUIT: u8 = 0x01;
+const COM_QUERY: u8 = 0x03;
+
+/// Largest packet sent or accepted
+const MAX_PACKET_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Error codes that mean the credentials were refused
+const AUTH_ERRORS: [u16; 4] = [
+    1044, // ER_DBACCESS_DENIED_ERROR
+    1045, // ER_ACCESS_DENIED_ERROR
+    1251, // ER_NOT_SUPPORTED_AUTH_MODE
+    1698, // ER_ACCESS_DENIED_NO_PASSWORD_ERROR
+];
+
+/// Logs in to a mysql server and runs a trivial query
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let port = check.port.unwrap_or(DEFAULT_PORT);
+    let result = credentials(check).and_then(|(user, password)| {
+        let mut stream = connect(host, port, timeout(check, DEFAULT_TIMEOUT))?;
+        let database = check.database.name.as_deref();
+        login(&mut stream, &user, &password, database)?;
+        query(&mut stream, QUERY)?;
+        // the server closes the connection without answering
+        let _ = write_packet(&mut stream, 0, &[COM_QUIT]);
+        Ok(())
+    });
+    match result {
+        Ok(()) => CheckResult::success(start.elapsed()),
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+fn credentials(check: &HealthCheck) -> Result<(String, String), Failure> {
+    let db = &check.database;
+    // validation makes sure mysql checks have a user
+    let user = db.user.as_ref().map(|u| u.resolve()).transpose()?;
+    let password = db.password.as_ref().map(|p| p.resolve()).transpose()?;
+    Ok((user.unwrap_or_default(), password.unwrap_or_default()))
+}
+
+/// Answers the initial handshake of the server and any authentication
+/// requests that follow, until the server accepts the login
+fn login<S: Read + Write>(
+    stream: &mut S,
+    user: &str,
+    password: &str,
+    database: Option<&str>,
+) -> Result<(), Failure> {
+    let (mut seq, handshake) = read_packet(stream)?;
+    let (mut plugin, mut nonce) = parse_handshake(&handshake)?;
+    if scramble(&plugin, password, &nonce).is_none() {
+        // the server switches to the plugin of the user if it needs another
+        plugin = "mysql_native_password".to_owned();
+    }
+
+    let mut capabilities = CLIENT_LONG_PASSWORD
+        | CLIENT_PROTOCOL_41
+        | CLIENT_TRANSACTIONS
+        | CLIENT_SECURE_CONNECTION
+        | CLIENT_PLUGIN_AUTH;
+    if database.is_some() {
+        capabilities |= CLIENT_CONNECT_WITH_DB;
+    }
+    let auth = scramble(&plugin, password, &nonce).unwrap_or_default();
+    let mut response = capabilities.to_le_bytes().to_vec();
+    response.extend_from_slice(&MAX_PACKET_SIZE.to_le_bytes());
+    response.push(CHARSET);
+    response.extend_from_slice(&[0; 23]);
+    put_cstr(&mut response, user);
+    response.push(auth.len() as u8);
+    response.extend_from_slice(&auth);
+    if let Some(database) = database {
+        put_cstr(&mut response, database);
+    }
+    put_cstr(&mut response, &plugin);
+    seq = seq.wrapping_add(1);
+    write_packet(stream, seq, &response)?;
+
+    loop {
+        let (last, packet) = read_packet(stream)?;
+        seq = last.wrapping_add(1);
+        match packet.first() {
+            Some(0x00) => return Ok(()),
+            Some(0xff) => return Err(server_error(&packet)),
+            // auth switch request
+            Some(0xfe) => {
+                let mut fields = packet[1..].splitn(2, |b| *b == 0);
+                plugin = String::from_utf8_lossy(fields.next().unwrap_or_default()).into_owned();
+                nonce = strip_nul(fields.next().unwrap_or_default()).to_vec();
+                let auth = scramble(&plugin, password, &nonce).ok_or_else(|| {
+                    Failure::Auth(format!("unsupported authentication plugin {}", plugin))
+                })?;
+                write_packet(stream, seq, &auth)?;
+            }
+            // more data for caching_sha2_password
+            Some(0x01) => match packet.get(1) {
+                // fast authentication succeeded, an OK packet follows
+                Some(0x03) => {}
+                Some(0x04) => {
+                    return Err(Failure::Auth(
+                        "caching_sha2_password needs full authentication, which takes TLS"
+                            .to_owned(),
+                    ))
+                }
+                _ => return Err(Failure::Protocol("unexpected auth data".to_owned())),
+            },
+            _ => return Err(Failure::Protocol("unexpected auth response".to_owned())),
+        }
+    }
+}
+
+/// Reads the auth plugin name and the nonce out of the initial handshake
+fn parse_handshake(packet: &[u8]) -> Result<(String, Vec<u8>), Failure> {
+    let truncated = || Failure::Protocol("truncated handshake".to_owned());
+    match packet.first() {
+        Some(10) => {}
+        Some(0xff) => return Err(server_error(packet)),
+        Some(version) => {
+            return Err(Failure::Protocol(format!(
+                "unsupported protocol version {}",
+                version
+            )))
+        }
+        None => return Err(truncated()),
+    }
+    // skip the server version and the connection id
+    let version_end = packet[1..]
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(truncated)?;
+    let rest = packet.get(version_end + 6..).ok_or_else(truncated)?;
+    let mut nonce = rest.get(..8).ok_or_else(truncated)?.to_vec();
+    // filler, capabilities, charset, status, capabilities, auth data length
+    // and reserved bytes
+    let rest = rest
+        .get(8 + 1 + 2 + 1 + 2 + 2 + 1 + 10..)
+        .unwrap_or_default();
+    let second = rest.get(..13).unwrap_or(rest);
+    nonce.extend_from_slice(strip_nul(second));
+    let plugin = rest
+        .get(13..)
+        .map(|p| String::from_utf8_lossy(strip_nul(p)).into_owned())
+        .unwrap_or_default();
+    Ok((plugin, nonce))
+}
+
+/// Proof of the password an auth plugin expects, if the plugin is supported
+fn scramble(plugin: &str, password: &str, nonce: &[u8]) -> Option<Vec<u8>> {
+    if password.is_empty() {
+        return Some(Vec::new());
+    }
+    let (algorithm, nonce_first) = match plugin {
+        // SHA1(password) XOR SHA1(nonce + SHA1(SHA1(password)))
+        "mysql_native_password" => (&digest::SHA1_FOR_LEGACY_USE_ONLY, true),
+        // SHA256(password) XOR SHA256(SHA256(SHA256(password)) + nonce)
+        "caching_sha2_password" => (&digest::SHA256, false),
+        _ => return None,
+    };
+    let hash = |parts: &[&[u8]]| {
+        let mut ctx = digest::Context::new(algorithm);
+        parts.iter().for_each(|p| ctx.update(p));
+        ctx.finish()
+    };
+    let once = hash(&[password.as_bytes()]);
+    let twice = hash(&[once.as_ref()]);
+    let salted = if nonce_first {
+        hash(&[nonce, twice.as_ref()])
+    } else {
+        hash(&[twice.as_ref(), nonce])
+    };
+    Some(
+        once.as_ref()
+            .iter()
+            .zip(salted.as_ref())
+            .map(|(a, b)| a ^ b)
+            .collect(),
+    )
+}
+
+/// Runs a query and reads the whole result set
+fn query<S: Read + Write>(stream: &mut S, sql: &str) -> Result<(), Failure> {
+    let mut command = vec![COM_QUERY];
+    command.extend_from_slice(sql.as_bytes());
+    write_packet(stream, 0, &command)?;
+    let (_, packet) = read_packet(stream)?;
+    match packet.first() {
+        Some(0x00) => return Ok(()),
+        Some(0xff) => return Err(server_error(&packet)),
+        _ => {}
+    }
+    // column definitions and rows each end with an EOF packet
+    let mut eofs = 0;
+    while eofs < 2 {
+        let (_, packet) = read_packet(stream)?;
+        match packet.first() {
+            Some(0xfe) if packet.len() < 9 => eofs += 1,
+            Some(0xff) => return Err(server_error(&packet)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Turns an ERR packet into a failure
+fn server_error(packet: &[u8]) -> Failure {
+    let code = packet
+        .get(1..3)
+        .map_or(0, |c| u16::from_le_bytes([c[0], c[1]]));
+    let mut text = packet.get(3..).unwrap_or_default();
+    // the SQL state follows a marker
+    if text.first() == Some(&b'#') {
+        text = text.get(6..).unwrap_or_default();
+    }
+    let text = String::from_utf8_lossy(text);
+    if AUTH_ERRORS.contains(&code) {
+        Failure::Auth(text.into_owned())
+    } else {
+        Failure::Database(format!("{} ({})", text, code))
+    }
+}
+
+fn write_packet<W: Write>(wtr: &mut W, seq: u8, payload: &[u8]) -> Result<(), Failure> {
+    let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+    packet.push(seq);
+    packet.extend_from_slice(payload);
+    wtr.write_all(&packet)?;
+    Ok(())
+}
+
+/// Reads a packet, returning its sequence id and payload
+fn read_packet<R: Read>(rdr: &mut R) -> Result<(u8, Vec<u8>), Failure> {
+    let mut head = [0; 4];
+    rdr.read_exact(&mut head)?;
+    let len = u32::from_le_bytes([head[0], head[1], head[2], 0]);
+    let mut payload = vec![0; len as usize];
+    rdr.read_exact(&mut payload)?;
+    Ok((head[3], payload))
+}
+
+fn put_cstr(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+}
+
+fn strip_nul(bytes: &[u8]) -> &[u8] {
+    bytes.strip_suffix(&[0]).unwrap_or(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::decode_hex;
+    use std::{net::TcpListener, thread};
+
+    const NONCE: [u8; 20] = [
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+    ];
+
+    /// Stands in for a mysql server whose only user is griffin with password
+    /// s3cr3t. The server announces caching_sha2_password, and switches the
+    /// user to mysql_native_password like an older account would need.
+    fn serve_mysql() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = answer(&mut stream.unwrap());
+            }
+        });
+        port
+    }
+
+    fn answer<S: Read + Write>(stream: &mut S) -> Result<(), Failure> {
+        let mut handshake = vec![10];
+        put_cstr(&mut handshake, "8.0.36");
+        handshake.extend_from_slice(&7u32.to_le_bytes());
+        handshake.extend_from_slice(&NONCE[..8]);
+        handshake.push(0);
+        handshake.extend_from_slice(&0xf7ffu16.to_le_bytes());
+        handshake.push(CHARSET);
+        handshake.extend_from_slice(&2u16.to_le_bytes());
+        handshake.extend_from_slice(&0x00ffu16.to_le_bytes());
+        handshake.push(21);
+        handshake.extend_from_slice(&[0; 10]);
+        handshake.extend_from_slice(&NONCE[8..]);
+        handshake.push(0);
+        put_cstr(&mut handshake, "caching_sha2_password");
+        write_packet(stream, 0, &handshake)?;
+
+        let (_, response) = read_packet(stream)?;
+        let fields = &response[32..];
+        let user_end = fields.iter().position(|b| *b == 0).unwrap();
+        let user = String::from_utf8_lossy(&fields[..user_end]).into_owned();
+        let err = |code: u16, text: &str| {
+            let mut packet = vec![0xff];
+            packet.extend_from_slice(&code.to_le_bytes());
+            packet.extend_from_slice(b"#28000");
+            packet.extend_from_slice(text.as_bytes());
+            packet
+        };
+
+        let mut switch = vec![0xfe];
+        put_cstr(&mut switch, "mysql_native_password");
+        switch.extend_from_slice(&NONCE);
+        switch.push(0);
+        write_packet(stream, 2, &switch)?;
+        let (_, auth) = read_packet(stream)?;
+        if user != "griffin" || Some(auth) != scramble("mysql_native_password", "s3cr3t", &NONCE) {
+            let text = format!("Access denied for user '{}'@'localhost'", user);
+            return write_packet(stream, 4, &err(1045, &text));
+        }
+        write_packet(stream, 4, &[0, 0, 0, 2, 0, 0, 0])?;
+
+        let (_, command) = read_packet(stream)?;
+        if command != b"\x03SELECT 1" {
+            return write_packet(
+                stream,
+                1,
+                &err(1064, "You have an error in your SQL syntax"),
+            );
+        }
+        write_packet(stream, 1, &[1])?;
+        write_packet(
+            stream,
+            2,
+            b"\x03def\x00\x00\x00\x011\x00\x0c\x3f\x00\x01\x00\x00\x00\x08\x81\x00\x00\x00\x00",
+        )?;
+        write_packet(stream, 3, &[0xfe, 0, 0, 2, 0])?;
+        write_packet(stream, 4, b"\x011")?;
+        write_packet(stream, 5, &[0xfe, 0, 0, 2, 0])?;
+        Ok(())
+    }
+
+    fn mysql_check(port: u16, database: &str) -> HealthCheck {
+        let check = format!(
+            "{{method: mysql, port: {}, database: {{{}}}}}",
+            port, database
+        );
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn logs_in_and_queries() {
+        let port = serve_mysql();
+        let check = mysql_check(port, "user: griffin, password: s3cr3t, name: orders");
+        let result = super::check("127.0.0.1", &check);
+        assert!(result.is_success(), "{:?}", result.failure);
+    }
+
+    #[test]
+    fn tells_auth_failures_apart() {
+        let port = serve_mysql();
+        let check = mysql_check(port, "user: griffin, password: wrong");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Auth(
+                "Access denied for user 'griffin'@'localhost'".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn scrambles_passwords() {
+        assert_eq!(
+            scramble("mysql_native_password", "s3cr3t", &NONCE),
+            decode_hex("5b020dedd7e93970806c75727c9da7392a248157").ok()
+        );
+        assert_eq!(
+            scramble("caching_sha2_password", "s3cr3t", &NONCE),
+            decode_hex("eaaa1f23aa3a7aa171e525c43fe5fe33338896b259c9cd4c8827d484a76382fc").ok()
+        );
+        assert_eq!(
+            scramble("mysql_native_password", "", &NONCE),
+            Some(Vec::new())
+        );
+        assert_eq!(scramble("sha256_password", "s3cr3t", &NONCE), None);
+    }
+}
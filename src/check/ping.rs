@@ -0,0 +1,265 @@
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+use super::{resolve, timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// Number of probes sent by a single ping check
+pub const PING_COUNT: u16 = 3;
+
+/// Port probed when ICMP is not permitted and the check does not set one
+pub const DEFAULT_PORT: u16 = 80;
+
+/// Time to wait for each probe to be answered unless the check sets a timeout
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const PAYLOAD: &[u8] = b"griffin healthck";
+
+/// Pings a host with ICMP echo, falling back to TCP connects when ICMP sockets
+/// are not permitted
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let port = check.port.unwrap_or(DEFAULT_PORT);
+    let addr = match resolve(host, port) {
+        Ok(addrs) => addrs[0],
+        Err(failure) => return CheckResult::failure(start.elapsed(), failure),
+    };
+
+    let timeout = timeout(check, PROBE_TIMEOUT);
+    let rtts = match IcmpSocket::open(addr.ip(), timeout) {
+        Ok(socket) => socket.ping(PING_COUNT),
+        Err(e) => {
+            debug!("ICMP unavailable for {} ({}), using TCP connect", host, e);
+            tcp_ping(addr, PING_COUNT, timeout)
+        }
+    };
+    summarize(&rtts, PING_COUNT, timeout)
+}
+
+/// Builds a result out of round trip times of answered probes
+fn summarize(rtts: &[Duration], sent: u16, timeout: Duration) -> CheckResult {
+    let loss = (sent as usize - rtts.len()) as f32 * 100.0 / sent as f32;
+    let mut result = if rtts.is_empty() {
+        CheckResult::failure(timeout, Failure::Timeout)
+    } else {
+        let latency = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+        if loss > 0.0 {
+            CheckResult::warning(latency, Failure::PacketLoss(loss))
+        } else {
+            CheckResult::success(latency)
+        }
+    };
+    result.packet_loss = Some(loss);
+    result
+}
+
+/// Probes a socket address with TCP connects.
+///
+/// A refused connection still proves the host is up, so it counts as an answer.
+fn tcp_ping(addr: SocketAddr, count: u16, timeout: Duration) -> Vec<Duration> {
+    (0..count)
+        .filter_map(|_| {
+            let start = Instant::now();
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(_) => Some(start.elapsed()),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Some(start.elapsed()),
+                Err(_) => None,
+            }
+        })
+        .collect()
+}
+
+/// ICMP socket connected to a single host
+struct IcmpSocket {
+    socket: Socket,
+    v6: bool,
+    raw: bool,
+    ident: u16,
+    timeout: Duration,
+}
+
+impl IcmpSocket {
+    /// Opens an unprivileged datagram ICMP socket, or a raw one if that fails
+    fn open(ip: IpAddr, timeout: Duration) -> io::Result<Self> {
+        let (domain, protocol) = match ip {
+            IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+            IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+        };
+        let (socket, raw) = match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+            Ok(socket) => (socket, false),
+            Err(_) => (Socket::new(domain, Type::RAW, Some(protocol))?, true),
+        };
+        socket.connect(&SockAddr::from(SocketAddr::new(ip, 0)))?;
+        socket.set_write_timeout(Some(DEFAULT_TIMEOUT))?;
+        Ok(Self {
+            socket,
+            v6: ip.is_ipv6(),
+            raw,
+            ident: std::process::id() as u16,
+            timeout,
+        })
+    }
+
+    /// Sends echo requests one after another and collects round trip times
+    fn ping(mut self, count: u16) -> Vec<Duration> {
+        (0..count)
+            .filter_map(|seq| match self.echo(seq) {
+                Ok(rtt) => rtt,
+                Err(e) => {
+                    debug!("ICMP echo {} failed: {}", seq, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Sends one echo request and waits for its reply
+    fn echo(&mut self, seq: u16) -> io::Result<Option<Duration>> {
+        let request = echo_request(self.v6, self.ident, seq);
+        let start = Instant::now();
+        self.socket.write_all(&request)?;
+
+        let deadline = start + self.timeout;
+        let mut buf = [0u8; 1024];
+        loop {
+            // replies to other requests must not stretch the wait
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(left))?;
+            let n = match self.socket.read(&mut buf) {
+                Ok(n) => n,
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some((ident, reply_seq)) = parse_echo_reply(&buf[..n], self.v6) {
+                // the kernel rewrites identifiers of datagram ICMP sockets
+                if reply_seq == seq && (!self.raw || ident == self.ident) {
+                    return Ok(Some(start.elapsed()));
+                }
+            }
+        }
+    }
+}
+
+/// Builds an ICMP echo request packet
+fn echo_request(v6: bool, ident: u16, seq: u16) -> Vec<u8> {
+    let kind = if v6 {
+        ICMPV6_ECHO_REQUEST
+    } else {
+        ICMPV4_ECHO_REQUEST
+    };
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    // the kernel fills in ICMPv6 checksums since they cover the IPv6 header
+    if !v6 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+/// Gets identifier and sequence number of an echo reply
+fn parse_echo_reply(packet: &[u8], v6: bool) -> Option<(u16, u16)> {
+    // raw IPv4 sockets hand over the IP header as well
+    let packet = if !v6 && packet.first().map(|b| b >> 4) == Some(4) {
+        let header_len = (packet[0] & 0x0f) as usize * 4;
+        packet.get(header_len..)?
+    } else {
+        packet
+    };
+    if packet.len() < 8 {
+        return None;
+    }
+    let reply = if v6 {
+        ICMPV6_ECHO_REPLY
+    } else {
+        ICMPV4_ECHO_REPLY
+    };
+    if packet[0] != reply {
+        return None;
+    }
+    Some((
+        u16::from_be_bytes([packet[4], packet[5]]),
+        u16::from_be_bytes([packet[6], packet[7]]),
+    ))
+}
+
+/// Internet checksum as described in RFC 1071
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::Status;
+    use std::net::TcpListener;
+
+    #[test]
+    fn ping_localhost() {
+        let check: HealthCheck = serde_yaml::from_str("method: ping").unwrap();
+        let result = super::check("127.0.0.1", &check);
+        assert!(result.is_success());
+        assert_eq!(result.packet_loss, Some(0.0));
+    }
+
+    #[test]
+    fn tcp_ping_counts_open_and_refused_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap();
+        assert_eq!(tcp_ping(open, 2, PROBE_TIMEOUT).len(), 2);
+
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        assert_eq!(tcp_ping(closed, 2, PROBE_TIMEOUT).len(), 2);
+    }
+
+    #[test]
+    fn summarize_packet_loss() {
+        let result = summarize(&[Duration::from_millis(10)], 4, PROBE_TIMEOUT);
+        assert!(result.is_success());
+        assert_eq!(result.status, Status::Warning);
+        assert_eq!(result.packet_loss, Some(75.0));
+
+        let result = summarize(&[], 4, PROBE_TIMEOUT);
+        assert_eq!(result.failure, Some(Failure::Timeout));
+        assert_eq!(result.packet_loss, Some(100.0));
+    }
+
+    #[test]
+    fn echo_request_round_trip() {
+        let mut packet = echo_request(false, 7, 3);
+        assert_eq!(checksum(&packet), 0);
+        packet[0] = ICMPV4_ECHO_REPLY;
+        assert_eq!(parse_echo_reply(&packet, false), Some((7, 3)));
+    }
+}
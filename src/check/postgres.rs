@@ -0,0 +1,497 @@
+use std::{
+    io::{Read, Write},
+    time::Instant,
+};
+
+use md5::{Digest, Md5};
+use ring::{digest, hmac, pbkdf2, rand::SecureRandom};
+
+use super::{connect, timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// Port postgres listens on unless the check sets one
+const DEFAULT_PORT: u16 = 5432;
+
+/// Version 3.0 of the frontend/backend protocol
+const PROTOCOL_VERSION: i32 = 196_608;
+
+/// Query run once logged in
+const QUERY: &str = "SELECT 1";
+
+/// Largest message accepted from a host
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Most SCRAM iterations done for a server, far above the 4096 postgres
+/// uses by default
+const MAX_SCRAM_ITERATIONS: u32 = 1_000_000;
+
+/// Logs in to a postgres server and runs a trivial query
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let port = check.port.unwrap_or(DEFAULT_PORT);
+    let result = credentials(check).and_then(|(user, password, database)| {
+        let mut stream = connect(host, port, timeout(check, DEFAULT_TIMEOUT))?;
+        login(&mut stream, &user, &password, &database)?;
+        query(&mut stream, QUERY)?;
+        // the server doesn't answer, so a failure to say goodbye is fine
+        let _ = stream.write_all(&message(b'X', &[]));
+        Ok(())
+    });
+    match result {
+        Ok(()) => CheckResult::success(start.elapsed()),
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+/// User, password and database of a check. The database defaults to the
+/// user name like it does for psql.
+fn credentials(check: &HealthCheck) -> Result<(String, String, String), Failure> {
+    let db = &check.database;
+    // validation makes sure postgres checks have a user
+    let user = db.user.as_ref().map(|u| u.resolve()).transpose()?;
+    let user = user.unwrap_or_default();
+    let password = db.password.as_ref().map(|p| p.resolve()).transpose()?;
+    let database = db.name.clone().unwrap_or_else(|| user.clone());
+    Ok((user, password.unwrap_or_default(), database))
+}
+
+/// Sends the startup message and answers authentication requests until the
+/// server is ready for queries
+fn login<S: Read + Write>(
+    stream: &mut S,
+    user: &str,
+    password: &str,
+    database: &str,
+) -> Result<(), Failure> {
+    let mut startup = PROTOCOL_VERSION.to_be_bytes().to_vec();
+    for (key, value) in &[
+        ("user", user),
+        ("database", database),
+        ("application_name", "griffin"),
+    ] {
+        put_cstr(&mut startup, key);
+        put_cstr(&mut startup, value);
+    }
+    startup.push(0);
+    let mut msg = ((startup.len() + 4) as i32).to_be_bytes().to_vec();
+    msg.extend(startup);
+    stream.write_all(&msg)?;
+
+    let mut scram = None;
+    loop {
+        let (kind, body) = read_message(stream)?;
+        match kind {
+            b'R' => {
+                let code = i32_at(&body, 0)?;
+                let data = &body[4..];
+                match code {
+                    0 => {}
+                    3 => {
+                        let mut reply = Vec::new();
+                        put_cstr(&mut reply, password);
+                        stream.write_all(&message(b'p', &reply))?;
+                    }
+                    5 => {
+                        let mut reply = Vec::new();
+                        put_cstr(&mut reply, &md5_password(user, password, data));
+                        stream.write_all(&message(b'p', &reply))?;
+                    }
+                    10 => {
+                        let offers_scram = data
+                            .split(|b| *b == 0)
+                            .any(|mechanism| mechanism == b"SCRAM-SHA-256");
+                        if !offers_scram {
+                            return Err(Failure::Auth(
+                                "server offers no supported SASL mechanism".to_owned(),
+                            ));
+                        }
+                        // postgres takes the user from the startup message
+                        let client = Scram::new("", password, &nonce()?);
+                        let first = client.first();
+                        let mut reply = Vec::new();
+                        put_cstr(&mut reply, "SCRAM-SHA-256");
+                        reply.extend_from_slice(&(first.len() as i32).to_be_bytes());
+                        reply.extend_from_slice(first.as_bytes());
+                        stream.write_all(&message(b'p', &reply))?;
+                        scram = Some(client);
+                    }
+                    11 => {
+                        let client = scram.as_mut().ok_or_else(|| unexpected(kind))?;
+                        let last = client.last(&String::from_utf8_lossy(data))?;
+                        stream.write_all(&message(b'p', last.as_bytes()))?;
+                    }
+                    12 => {
+                        let client = scram.as_ref().ok_or_else(|| unexpected(kind))?;
+                        client.verify(&String::from_utf8_lossy(data))?;
+                    }
+                    code => {
+                        return Err(Failure::Auth(format!(
+                            "unsupported authentication method {}",
+                            code
+                        )))
+                    }
+                }
+            }
+            b'E' => return Err(server_error(&body)),
+            b'Z' => return Ok(()),
+            // parameter status, backend key data and notices
+            _ => {}
+        }
+    }
+}
+
+/// Runs a simple query and waits for the server to be ready again
+fn query<S: Read + Write>(stream: &mut S, sql: &str) -> Result<(), Failure> {
+    let mut body = Vec::new();
+    put_cstr(&mut body, sql);
+    stream.write_all(&message(b'Q', &body))?;
+    let mut error = None;
+    loop {
+        let (kind, body) = read_message(stream)?;
+        match kind {
+            b'E' => error = Some(server_error(&body)),
+            b'Z' => return error.map_or(Ok(()), Err),
+            _ => {}
+        }
+    }
+}
+
+/// Turns an ErrorResponse into a failure. Errors of class 28 are about
+/// authorization.
+fn server_error(body: &[u8]) -> Failure {
+    let mut code = "";
+    let mut text = "";
+    for field in body.split(|b| *b == 0).filter(|f| !f.is_empty()) {
+        let value = std::str::from_utf8(&field[1..]).unwrap_or_default();
+        match field[0] {
+            b'C' => code = value,
+            b'M' => text = value,
+            _ => {}
+        }
+    }
+    if code.starts_with("28") {
+        Failure::Auth(text.to_owned())
+    } else {
+        Failure::Database(format!("{} ({})", text, code))
+    }
+}
+
+fn unexpected(kind: u8) -> Failure {
+    Failure::Protocol(format!("unexpected message {:?}", kind as char))
+}
+
+fn message(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut msg = vec![kind];
+    msg.extend_from_slice(&((body.len() + 4) as i32).to_be_bytes());
+    msg.extend_from_slice(body);
+    msg
+}
+
+fn read_message<R: Read>(rdr: &mut R) -> Result<(u8, Vec<u8>), Failure> {
+    let mut head = [0; 5];
+    rdr.read_exact(&mut head)?;
+    let len = i32::from_be_bytes([head[1], head[2], head[3], head[4]]);
+    if len < 4 || len as usize > MAX_MESSAGE_SIZE {
+        return Err(Failure::Protocol(format!("invalid message length {}", len)));
+    }
+    let mut body = vec![0; len as usize - 4];
+    rdr.read_exact(&mut body)?;
+    Ok((head[0], body))
+}
+
+fn put_cstr(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+}
+
+fn i32_at(buf: &[u8], pos: usize) -> Result<i32, Failure> {
+    buf.get(pos..pos + 4)
+        .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| Failure::Protocol("truncated message".to_owned()))
+}
+
+/// Password hashed like `md5` authentication expects
+fn md5_password(user: &str, password: &str, salt: &[u8]) -> String {
+    let inner = hex(&Md5::digest(format!("{}{}", password, user)));
+    let mut outer = Md5::new();
+    outer.update(inner.as_bytes());
+    outer.update(salt);
+    format!("md5{}", hex(&outer.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Random client nonce of a SCRAM exchange
+fn nonce() -> Result<String, Failure> {
+    let mut bytes = [0; 18];
+    ring::rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Failure::Auth("could not generate a nonce".to_owned()))?;
+    Ok(base64::encode(bytes))
+}
+
+/// Client side of a SCRAM-SHA-256 exchange as described in RFC 5802 and
+/// RFC 7677, without channel binding
+struct Scram {
+    user: String,
+    password: String,
+    nonce: String,
+    /// The client-first and server-first messages along with the
+    /// client-final message without its proof
+    auth_message: String,
+    salted_password: Vec<u8>,
+}
+
+impl Scram {
+    fn new(user: &str, password: &str, nonce: &str) -> Self {
+        Self {
+            user: user.to_owned(),
+            password: password.to_owned(),
+            nonce: nonce.to_owned(),
+            auth_message: String::new(),
+            salted_password: Vec::new(),
+        }
+    }
+
+    /// The client-first message
+    fn first(&self) -> String {
+        format!("n,,n={},r={}", self.user, self.nonce)
+    }
+
+    /// Answers the server-first message with the client-final message
+    fn last(&mut self, server_first: &str) -> Result<String, Failure> {
+        let invalid = || Failure::Auth("invalid SCRAM challenge from server".to_owned());
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attr in server_first.split(',') {
+            match attr.split_at(attr.find('=').map_or(0, |i| i + 1)) {
+                ("r=", value) => nonce = Some(value),
+                ("s=", value) => salt = base64::decode(value).ok(),
+                ("i=", value) => iterations = value.parse::<u32>().ok(),
+                _ => {}
+            }
+        }
+        let nonce = nonce
+            .filter(|n| n.starts_with(&self.nonce))
+            .ok_or_else(invalid)?;
+        let salt = salt.ok_or_else(invalid)?;
+        // a huge count would keep hashing long after the check timed out
+        let iterations = iterations
+            .filter(|&i| i <= MAX_SCRAM_ITERATIONS)
+            .and_then(std::num::NonZeroU32::new)
+            .ok_or_else(invalid)?;
+
+        let mut salted = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            self.password.as_bytes(),
+            &mut salted,
+        );
+        let without_proof = format!("c=biws,r={}", nonce);
+        self.auth_message = format!("{},{},{}", &self.first()[3..], server_first, without_proof);
+        self.salted_password = salted.to_vec();
+
+        let client_key = sign(&salted, b"Client Key");
+        let stored_key = digest::digest(&digest::SHA256, &client_key);
+        let signature = sign(stored_key.as_ref(), self.auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(signature.iter())
+            .map(|(k, s)| k ^ s)
+            .collect();
+        Ok(format!("{},p={}", without_proof, base64::encode(proof)))
+    }
+
+    /// Makes sure the server-final message proves the server knows the
+    /// password too
+    fn verify(&self, server_final: &str) -> Result<(), Failure> {
+        if let Some(error) = server_final.strip_prefix("e=") {
+            return Err(Failure::Auth(error.to_owned()));
+        }
+        let server_key = sign(&self.salted_password, b"Server Key");
+        let expected = sign(&server_key, self.auth_message.as_bytes());
+        let signature = server_final
+            .strip_prefix("v=")
+            .and_then(|v| base64::decode(v).ok());
+        if signature.as_deref() != Some(&expected[..]) {
+            return Err(Failure::Auth(
+                "server sent an invalid SCRAM signature".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    /// Stands in for a postgres server that uses md5 authentication for the
+    /// user griffin with password s3cr3t and has a single database
+    fn serve_postgres() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = answer(&mut stream.unwrap());
+            }
+        });
+        port
+    }
+
+    fn answer<S: Read + Write>(stream: &mut S) -> Result<(), Failure> {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let mut startup = vec![0; i32::from_be_bytes(len) as usize - 4];
+        stream.read_exact(&mut startup)?;
+        let params: Vec<String> = startup[4..]
+            .split(|b| *b == 0)
+            .map(|p| String::from_utf8_lossy(p).into_owned())
+            .collect();
+        let param = |key: &str| {
+            let i = params.iter().position(|p| p == key).unwrap();
+            params[i + 1].clone()
+        };
+        let error = |code: &str, text: &str| {
+            let mut body = Vec::new();
+            for (field, value) in &[("S", "FATAL"), ("C", code), ("M", text)] {
+                put_cstr(&mut body, &format!("{}{}", field, value));
+            }
+            body.push(0);
+            message(b'E', &body)
+        };
+
+        let salt = [1, 2, 3, 4];
+        let mut challenge = 5i32.to_be_bytes().to_vec();
+        challenge.extend_from_slice(&salt);
+        stream.write_all(&message(b'R', &challenge))?;
+        let (_, password) = read_message(stream)?;
+        let expected = md5_password(&param("user"), "s3cr3t", &salt);
+        if password[..password.len() - 1] != *expected.as_bytes() {
+            let text = format!(
+                "password authentication failed for user \"{}\"",
+                param("user")
+            );
+            return Ok(stream.write_all(&error("28P01", &text))?);
+        }
+        if param("database") != "orders" {
+            let text = format!("database \"{}\" does not exist", param("database"));
+            return Ok(stream.write_all(&error("3D000", &text))?);
+        }
+        let mut msg = message(b'R', &0i32.to_be_bytes());
+        msg.extend(message(b'S', b"server_version\x0016.0\x00"));
+        msg.extend(message(b'Z', b"I"));
+        stream.write_all(&msg)?;
+
+        let (kind, sql) = read_message(stream)?;
+        assert_eq!((kind, &sql[..]), (b'Q', &b"SELECT 1\x00"[..]));
+        let mut msg = message(b'T', b"\x00\x01?column?\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x17\x00\x04\xff\xff\xff\xff\x00\x00");
+        msg.extend(message(b'D', b"\x00\x01\x00\x00\x00\x011"));
+        msg.extend(message(b'C', b"SELECT 1\x00"));
+        msg.extend(message(b'Z', b"I"));
+        stream.write_all(&msg)?;
+        Ok(())
+    }
+
+    fn postgres_check(port: u16, database: &str) -> HealthCheck {
+        let check = format!(
+            "{{method: postgres, port: {}, database: {{{}}}}}",
+            port, database
+        );
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn logs_in_and_queries() {
+        let port = serve_postgres();
+        let check = postgres_check(port, "user: griffin, password: s3cr3t, name: orders");
+        let result = super::check("127.0.0.1", &check);
+        assert!(result.is_success(), "{:?}", result.failure);
+    }
+
+    #[test]
+    fn tells_auth_failures_apart() {
+        let port = serve_postgres();
+        let check = postgres_check(port, "user: griffin, password: wrong, name: orders");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Auth(
+                "password authentication failed for user \"griffin\"".to_owned()
+            ))
+        );
+
+        // the database defaults to the user name
+        let check = postgres_check(port, "user: griffin, password: s3cr3t");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Database(
+                "database \"griffin\" does not exist (3D000)".to_owned()
+            ))
+        );
+
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let check = postgres_check(port, "user: griffin");
+        assert!(matches!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Connect(_))
+        ));
+    }
+
+    #[test]
+    fn scram_sha_256_exchange() {
+        // test vector of RFC 7677
+        let mut scram = Scram::new("user", "pencil", "rOprNGfwEbeRWgbNEkqO");
+        assert_eq!(scram.first(), "n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+        let server_first =
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        assert_eq!(
+            scram.last(server_first),
+            Ok(
+                "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            scram.verify("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="),
+            Ok(())
+        );
+        assert!(scram.verify("v=AAAA").is_err());
+        assert_eq!(
+            scram.verify("e=invalid-proof"),
+            Err(Failure::Auth("invalid-proof".to_owned()))
+        );
+        assert!(scram.last("r=someone-else,s=AAAA,i=4096").is_err());
+        let mut scram = Scram::new("user", "pencil", "rOprNGfwEbeRWgbNEkqO");
+        assert_eq!(
+            scram.last("r=rOprNGfwEbeRWgbNEkqO%hvYD,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4294967295"),
+            Err(Failure::Auth(
+                "invalid SCRAM challenge from server".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn hashes_md5_passwords() {
+        // select 'md5' || md5(md5('s3cr3t' || 'griffin') || 'salt')
+        assert_eq!(
+            md5_password("griffin", "s3cr3t", b"salt"),
+            "md5fda1f6d79078f57cdad20d133f1e0062"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    io::{Read, Write},
+    time::Instant,
+};
+
+use super::{connect, timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// Port redis listens on unless the check sets one
+const DEFAULT_PORT: u16 = 6379;
+
+/// Longest reply line accepted from a host
+const MAX_LINE_SIZE: usize = 64 * 1024;
+
+/// Logs in to a redis server when the check has a password, selects its
+/// database and sends a PING
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let port = check.port.unwrap_or(DEFAULT_PORT);
+    let result = connect(host, port, timeout(check, DEFAULT_TIMEOUT)).and_then(|mut stream| {
+        let db = &check.database;
+        let user = db.user.as_ref().map(|u| u.resolve()).transpose()?;
+        if let Some(password) = db.password.as_ref().map(|p| p.resolve()).transpose()? {
+            let mut auth = vec!["AUTH"];
+            auth.extend(user.as_deref());
+            auth.push(&password);
+            command(&mut stream, &auth).map_err(|e| match e {
+                Failure::Database(e) => Failure::Auth(e),
+                e => e,
+            })?;
+        }
+        if let Some(name) = &db.name {
+            command(&mut stream, &["SELECT", name])?;
+        }
+        match command(&mut stream, &["PING"])?.as_str() {
+            "PONG" => {}
+            reply => {
+                return Err(Failure::Protocol(format!(
+                    "unexpected reply {:?} to PING",
+                    reply
+                )))
+            }
+        }
+        let _ = command(&mut stream, &["QUIT"]);
+        Ok(())
+    });
+    match result {
+        Ok(()) => CheckResult::success(start.elapsed()),
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+/// Sends a command and reads its simple string reply
+fn command<S: Read + Write>(stream: &mut S, args: &[&str]) -> Result<String, Failure> {
+    let mut msg = format!("*{}\r\n", args.len());
+    for arg in args {
+        msg.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream.write_all(msg.as_bytes())?;
+
+    let line = read_line(stream)?;
+    let (kind, reply) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+    match kind {
+        "+" => Ok(reply.to_owned()),
+        "-" if reply.starts_with("NOAUTH") || reply.starts_with("WRONGPASS") => {
+            Err(Failure::Auth(reply.to_owned()))
+        }
+        "-" => Err(Failure::Database(reply.to_owned())),
+        _ => Err(Failure::Protocol(format!(
+            "unexpected reply {:?} to {}",
+            line, args[0]
+        ))),
+    }
+}
+
+/// Reads a line ending in CRLF without reading past it
+fn read_line<R: Read>(rdr: &mut R) -> Result<String, Failure> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while !line.ends_with(b"\r\n") {
+        rdr.read_exact(&mut byte)?;
+        line.push(byte[0]);
+        if line.len() > MAX_LINE_SIZE {
+            return Err(Failure::Protocol("reply line is too long".to_owned()));
+        }
+    }
+    line.truncate(line.len() - 2);
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Stands in for a redis server with the password s3cr3t and 16
+    /// databases. Only the argument lines of requests are kept.
+    fn serve_redis() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut rdr = BufReader::new(stream.try_clone().unwrap());
+                let mut authenticated = false;
+                loop {
+                    let mut args = Vec::new();
+                    let mut line = String::new();
+                    if rdr.read_line(&mut line).unwrap_or_default() == 0 {
+                        break;
+                    }
+                    let count: usize = line.trim()[1..].parse().unwrap();
+                    for _ in 0..count * 2 {
+                        line.clear();
+                        rdr.read_line(&mut line).unwrap();
+                        if !line.starts_with('$') {
+                            args.push(line.trim().to_owned());
+                        }
+                    }
+                    let reply = match args[0].as_str() {
+                        "AUTH" if args.last().unwrap() == "s3cr3t" => {
+                            authenticated = true;
+                            "+OK"
+                        }
+                        "AUTH" => "-WRONGPASS invalid username-password pair or user is disabled.",
+                        "QUIT" => "+OK",
+                        _ if !authenticated => "-NOAUTH Authentication required.",
+                        "SELECT" if args[1].parse::<u8>().is_ok_and(|i| i < 16) => "+OK",
+                        "SELECT" => "-ERR DB index is out of range",
+                        "PING" => "+PONG",
+                        _ => "-ERR unknown command",
+                    };
+                    let _ = stream.write_all(format!("{}\r\n", reply).as_bytes());
+                }
+            }
+        });
+        port
+    }
+
+    fn redis_check(port: u16, database: &str) -> HealthCheck {
+        let check = format!(
+            "{{method: redis, port: {}, database: {{{}}}}}",
+            port, database
+        );
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn authenticates_and_pings() {
+        let port = serve_redis();
+        let check = redis_check(port, "password: s3cr3t, name: '3'");
+        let result = super::check("127.0.0.1", &check);
+        assert!(result.is_success(), "{:?}", result.failure);
+
+        let check = redis_check(port, "user: default, password: s3cr3t");
+        assert!(super::check("127.0.0.1", &check).is_success());
+    }
+
+    #[test]
+    fn tells_auth_failures_apart() {
+        let port = serve_redis();
+        let check = redis_check(port, "password: wrong");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Auth(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_owned()
+            ))
+        );
+
+        let check = redis_check(port, "");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Auth("NOAUTH Authentication required.".to_owned()))
+        );
+
+        let check = redis_check(port, "password: s3cr3t, name: '16'");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Database("ERR DB index is out of range".to_owned()))
+        );
+    }
+
+    #[test]
+    fn reads_single_lines() {
+        let mut rdr = "+OK\r\n+PONG\r\n".as_bytes();
+        assert_eq!(read_line(&mut rdr), Ok("+OK".to_owned()));
+        assert_eq!(read_line(&mut rdr), Ok("+PONG".to_owned()));
+        assert!(read_line(&mut rdr).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    io::{self, Read, Write},
+    time::Instant,
+};
+
+use regex::bytes::Regex;
+
+use super::{connect, payload, timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// Most data read from a host while waiting for the banner
+const MAX_BANNER_SIZE: usize = 64 * 1024;
+
+/// Longest part of a banner shown when it doesn't match
+const SHOWN_BANNER_SIZE: usize = 100;
+
+/// Connects to a port of a host, optionally sending a payload and matching
+/// what the host answers against the banner pattern
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    // validation makes sure tcp checks have a port
+    let port = check.port.unwrap_or_default();
+    let result = connect(host, port, timeout(check, DEFAULT_TIMEOUT)).and_then(|mut stream| {
+        let payload = payload(check)?;
+        if !payload.is_empty() {
+            stream.write_all(&payload)?;
+        }
+        match &check.banner {
+            Some(re) => read_banner(&mut stream, re),
+            None => Ok(()),
+        }
+    });
+    match result {
+        Ok(()) => CheckResult::success(start.elapsed()),
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+/// Reads from a stream until what was read matches a pattern
+fn read_banner<R: Read>(rdr: &mut R, re: &Regex) -> Result<(), Failure> {
+    let mut banner = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let n = match rdr.read(&mut buf) {
+            Ok(n) => n,
+            // whatever arrived before the host went quiet is all we get
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                0
+            }
+            Err(e) => return Err(e.into()),
+        };
+        banner.extend_from_slice(&buf[..n]);
+        if re.is_match(&banner) {
+            return Ok(());
+        }
+        if n == 0 || banner.len() >= MAX_BANNER_SIZE {
+            let shown: String = String::from_utf8_lossy(&banner)
+                .chars()
+                .take(SHOWN_BANNER_SIZE)
+                .collect();
+            return Err(Failure::Assertion(format!(
+                "banner {:?} does not match /{}/",
+                shown,
+                re.as_str()
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serves a tiny line based protocol that answers PING with +PONG
+    fn serve_pong() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut line = String::new();
+                let _ = BufReader::new(&mut stream).read_line(&mut line);
+                let answer = match line.as_str() {
+                    "PING\r\n" => "+PONG\r\n",
+                    _ => "-ERR unknown command\r\n",
+                };
+                let _ = stream.write_all(answer.as_bytes());
+            }
+        });
+        port
+    }
+
+    fn tcp_check(port: u16, extra: &str) -> HealthCheck {
+        let check = format!("{{method: tcp, port: {}{}}}", port, extra);
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn connects_to_open_port() {
+        let port = serve_pong();
+        assert!(check("127.0.0.1", &tcp_check(port, "")).is_success());
+    }
+
+    #[test]
+    fn fails_on_closed_port() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let result = check("127.0.0.1", &tcp_check(port, ""));
+        assert!(matches!(result.failure, Some(Failure::Connect(_))));
+    }
+
+    #[test]
+    fn matches_banner() {
+        let port = serve_pong();
+        let check = tcp_check(port, r#", payload: "PING\r\n", banner: ^\+PONG"#);
+        assert!(super::check("127.0.0.1", &check).is_success());
+
+        let check = tcp_check(port, r#", payload: "HELLO\r\n", banner: ^\+PONG"#);
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Assertion(
+                r#"banner "-ERR unknown command\r\n" does not match /^\+PONG/"#.to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn reads_banner_in_pieces() {
+        let re = Regex::new("220 .* ESMTP").unwrap();
+        let mut rdr = "220 mail.example.com"
+            .as_bytes()
+            .chain(" ESMTP ready\r\n".as_bytes());
+        assert!(read_banner(&mut rdr, &re).is_ok());
+        let mut rdr = "421 mail.example.com busy\r\n".as_bytes();
+        assert!(read_banner(&mut rdr, &re).is_err());
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    convert::TryFrom,
+    fs,
+    io::{self, BufReader, Read, Write},
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, PrivateKey, RootCertStore,
+    ServerName, StreamOwned,
+};
+
+use super::{connect, Failure};
+use crate::config::TlsOptions;
+
+/// A TLS connection to a host
+pub struct TlsStream(StreamOwned<ClientConnection, TcpStream>);
+
+impl TlsStream {
+    /// Certificate chain the host presented, leaf first
+    pub fn peer_certificates(&self) -> &[Certificate] {
+        self.0.conn.peer_certificates().unwrap_or(&[])
+    }
+
+    /// Application protocol agreed on with ALPN
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.0.conn.alpn_protocol()
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            // plenty of servers close the connection without a close_notify
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            other => other,
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Opens a TCP connection to a host and completes a TLS handshake on it
+pub fn connect_tls(
+    host: &str,
+    port: u16,
+    opts: &TlsOptions,
+    timeout: Duration,
+) -> Result<TlsStream, Failure> {
+    connect_tls_alpn(host, port, opts, &[], timeout)
+}
+
+/// Like `connect_tls`, offering application protocols like `h2` with ALPN
+pub fn connect_tls_alpn(
+    host: &str,
+    port: u16,
+    opts: &TlsOptions,
+    protocols: &[&[u8]],
+    timeout: Duration,
+) -> Result<TlsStream, Failure> {
+    let config = client_config(opts, protocols)?;
+    let name = opts.server_name.as_deref().unwrap_or(host);
+    let server_name = ServerName::try_from(name)
+        .map_err(|_| Failure::Tls(format!("invalid server name {:?}", name)))?;
+    let mut conn =
+        ClientConnection::new(config, server_name).map_err(|e| Failure::Tls(e.to_string()))?;
+
+    let mut sock = connect(host, port, timeout)?;
+    while conn.is_handshaking() {
+        if let Err(e) = conn.complete_io(&mut sock) {
+            return Err(match e.kind() {
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Failure::Timeout,
+                io::ErrorKind::UnexpectedEof => {
+                    Failure::Tls("connection closed during handshake".to_owned())
+                }
+                _ => Failure::Tls(e.to_string()),
+            });
+        }
+    }
+    Ok(TlsStream(StreamOwned::new(conn, sock)))
+}
+
+/// Builds a rustls client config out of the TLS options of a check
+fn client_config(opts: &TlsOptions, protocols: &[&[u8]]) -> Result<Arc<ClientConfig>, Failure> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let verifier: Arc<dyn ServerCertVerifier> = if opts.insecure_skip_verify {
+        Arc::new(NoVerification)
+    } else {
+        Arc::new(WebPkiVerifier::new(
+            root_store(opts.ca_file.as_deref())?,
+            None,
+        ))
+    };
+    let builder = builder.with_custom_certificate_verifier(verifier);
+    let mut config = match (&opts.client_cert, &opts.client_key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+            .map_err(|e| Failure::Tls(format!("invalid client certificate: {}", e)))?,
+        _ => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = protocols.iter().map(|p| p.to_vec()).collect();
+    Ok(Arc::new(config))
+}
+
+/// Trusted CAs, either from a PEM bundle or the built in Mozilla roots
+fn root_store(ca_file: Option<&Path>) -> Result<RootCertStore, Failure> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(path) => {
+            for cert in read_certs(path)? {
+                roots.add(&cert).map_err(|e| {
+                    Failure::Tls(format!("invalid CA in {}: {}", path.display(), e))
+                })?;
+            }
+        }
+        None => roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        })),
+    }
+    Ok(roots)
+}
+
+/// Reads every certificate of a PEM file
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, Failure> {
+    let file = fs::File::open(path)
+        .map_err(|e| Failure::Tls(format!("could not read {}: {}", path.display(), e)))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| Failure::Tls(format!("could not read {}: {}", path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(Failure::Tls(format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first private key of a PEM file
+fn read_key(path: &Path) -> Result<PrivateKey, Failure> {
+    let file = fs::File::open(path)
+        .map_err(|e| Failure::Tls(format!("could not read {}: {}", path.display(), e)))?;
+    let mut rdr = BufReader::new(file);
+    loop {
+        match rustls_pemfile::read_one(&mut rdr) {
+            Ok(Some(rustls_pemfile::Item::PKCS8Key(key)))
+            | Ok(Some(rustls_pemfile::Item::RSAKey(key)))
+            | Ok(Some(rustls_pemfile::Item::ECKey(key))) => return Ok(PrivateKey(key)),
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return Err(Failure::Tls(format!(
+                    "no private key found in {}",
+                    path.display()
+                )))
+            }
+            Err(e) => {
+                return Err(Failure::Tls(format!(
+                    "could not read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        }
+    }
+}
+
+/// Accepts every server certificate, for `insecure_skip_verify`
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, SanType};
+    use rustls::{server::AllowAnyAuthenticatedClient, ServerConfig, ServerConnection};
+    use std::{
+        io::BufRead,
+        net::TcpListener,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    static DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// A throwaway CA with a server certificate for localhost and a client
+    /// certificate, written to a temporary directory
+    pub struct TestPki {
+        pub ca_file: PathBuf,
+        pub client_cert: PathBuf,
+        pub client_key: PathBuf,
+        server_certs: Vec<Certificate>,
+        server_key: PrivateKey,
+        ca_cert: Certificate,
+    }
+
+    impl TestPki {
+        pub fn new() -> Self {
+            Self::with_server(server_params())
+        }
+
+        /// Like `new`, with the server certificate made from `params`
+        pub fn with_server(params: CertificateParams) -> Self {
+            let server = rcgen::Certificate::from_params(params).unwrap();
+            let dir = std::env::temp_dir().join(format!(
+                "griffin-test-{}-{}",
+                std::process::id(),
+                DIR_COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+
+            let mut params = CertificateParams::new(vec![]);
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params
+                .distinguished_name
+                .push(DnType::CommonName, "Griffin Test CA");
+            let ca = rcgen::Certificate::from_params(params).unwrap();
+
+            let client =
+                rcgen::Certificate::from_params(CertificateParams::new(vec!["griffin".to_owned()]))
+                    .unwrap();
+
+            let pki = Self {
+                ca_file: dir.join("ca.pem"),
+                client_cert: dir.join("client.pem"),
+                client_key: dir.join("client.key"),
+                server_certs: vec![Certificate(server.serialize_der_with_signer(&ca).unwrap())],
+                server_key: PrivateKey(server.serialize_private_key_der()),
+                ca_cert: Certificate(ca.serialize_der().unwrap()),
+            };
+            fs::write(&pki.ca_file, ca.serialize_pem().unwrap()).unwrap();
+            fs::write(
+                &pki.client_cert,
+                client.serialize_pem_with_signer(&ca).unwrap(),
+            )
+            .unwrap();
+            fs::write(&pki.client_key, client.serialize_private_key_pem()).unwrap();
+            pki
+        }
+
+        /// Serves a canned response over TLS to every connection on a random
+        /// local port, optionally requiring a client certificate
+        pub fn serve(&self, response: &'static str, require_client_cert: bool) -> u16 {
+            let config = Arc::new(self.server_config(require_client_cert));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            thread::spawn(move || {
+                for sock in listener.incoming() {
+                    let conn = ServerConnection::new(Arc::clone(&config)).unwrap();
+                    let mut stream = StreamOwned::new(conn, sock.unwrap());
+                    let _ = answer(&mut stream, response);
+                }
+            });
+            port
+        }
+
+        /// Server side config presenting the server certificate
+        pub fn server_config(&self, require_client_cert: bool) -> ServerConfig {
+            let builder = ServerConfig::builder().with_safe_defaults();
+            let builder = if require_client_cert {
+                let mut roots = RootCertStore::empty();
+                roots.add(&self.ca_cert).unwrap();
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            } else {
+                builder.with_no_client_auth()
+            };
+            builder
+                .with_single_cert(self.server_certs.clone(), self.server_key.clone())
+                .unwrap()
+        }
+    }
+
+    /// Parameters of a server certificate for localhost and 127.0.0.1
+    pub fn server_params() -> CertificateParams {
+        let mut params = CertificateParams::new(vec!["localhost".to_owned()]);
+        params
+            .subject_alt_names
+            .push(SanType::IpAddress("127.0.0.1".parse().unwrap()));
+        params
+    }
+
+    fn answer(
+        stream: &mut StreamOwned<ServerConnection, TcpStream>,
+        response: &str,
+    ) -> io::Result<()> {
+        let mut rdr = BufReader::new(&mut *stream);
+        let mut line = String::new();
+        while rdr.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+        stream.write_all(response.as_bytes())?;
+        stream.conn.send_close_notify();
+        stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::testing::TestPki;
+    use super::*;
+
+    #[test]
+    fn verifies_certificates_against_ca_file() {
+        let pki = TestPki::new();
+        let port = pki.serve("HTTP/1.1 200 OK\r\n\r\n", false);
+        let opts = TlsOptions {
+            ca_file: Some(pki.ca_file.clone()),
+            ..Default::default()
+        };
+        assert!(connect_tls("127.0.0.1", port, &opts, Duration::from_secs(5)).is_ok());
+
+        // the built in roots don't know the test CA
+        let failure = connect_tls(
+            "localhost",
+            port,
+            &TlsOptions::default(),
+            Duration::from_secs(5),
+        );
+        assert!(matches!(failure, Err(Failure::Tls(_))));
+
+        let opts = TlsOptions {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        assert!(connect_tls("localhost", port, &opts, Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn verifies_server_name_override() {
+        let pki = TestPki::new();
+        let port = pki.serve("HTTP/1.1 200 OK\r\n\r\n", false);
+        let opts = TlsOptions {
+            ca_file: Some(pki.ca_file.clone()),
+            server_name: Some("api.example.com".to_owned()),
+            ..Default::default()
+        };
+        match connect_tls("127.0.0.1", port, &opts, Duration::from_secs(5)) {
+            Err(Failure::Tls(e)) => assert!(e.contains("NotValidForName"), "{}", e),
+            _ => panic!("certificate should not be valid for api.example.com"),
+        }
+    }
+
+    #[test]
+    fn presents_client_certificate() {
+        let pki = TestPki::new();
+        let port = pki.serve("HTTP/1.1 200 OK\r\n\r\n", true);
+        let mut opts = TlsOptions {
+            ca_file: Some(pki.ca_file.clone()),
+            ..Default::default()
+        };
+        let mut without_cert = connect_tls("localhost", port, &opts, Duration::from_secs(5))
+            .map_err(|_| ())
+            .unwrap();
+        // TLS 1.3 servers reject missing client certificates after the handshake
+        let mut buf = Vec::new();
+        let _ = without_cert.write_all(b"GET / HTTP/1.1\r\n\r\n");
+        assert!(without_cert.0.read_to_end(&mut buf).is_err());
+
+        opts.client_cert = Some(pki.client_cert.clone());
+        opts.client_key = Some(pki.client_key.clone());
+        let mut stream = connect_tls("localhost", port, &opts, Duration::from_secs(5))
+            .map_err(|_| ())
+            .unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        stream.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"HTTP/1.1 200 OK\r\n\r\n");
+    }
+}
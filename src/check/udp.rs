@@ -0,0 +1,175 @@
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use super::{payload, resolve, timeout, CheckResult, Failure, DEFAULT_TIMEOUT};
+use crate::config::HealthCheck;
+
+/// Largest datagram read from a host
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+/// Longest part of a response shown when it doesn't match
+const SHOWN_RESPONSE_SIZE: usize = 100;
+
+/// Sends the payload in a datagram to a port of a host and waits for a
+/// response, which must match the banner pattern when one is set
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    // validation makes sure udp checks have a port
+    let port = check.port.unwrap_or_default();
+    let result = resolve(host, port)
+        .and_then(|addrs| probe(addrs[0], check, timeout(check, DEFAULT_TIMEOUT)));
+    match result {
+        Ok(()) => CheckResult::success(start.elapsed()),
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+/// Sends the payload and reads responses until one matches or time runs out
+fn probe(addr: SocketAddr, check: &HealthCheck, timeout: Duration) -> Result<(), Failure> {
+    let local: SocketAddr = match addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(addr)?;
+    socket.send(&payload(check)?).map_err(unreachable)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    let mut last = None;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            break;
+        }
+        socket.set_read_timeout(Some(left))?;
+        let n = match socket.recv(&mut buf) {
+            Ok(n) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                break;
+            }
+            Err(e) => return Err(unreachable(e)),
+        };
+        match &check.banner {
+            Some(re) if !re.is_match(&buf[..n]) => last = Some(buf[..n].to_vec()),
+            _ => return Ok(()),
+        }
+    }
+
+    match (last, &check.banner) {
+        (Some(response), Some(re)) => {
+            let shown: String = String::from_utf8_lossy(&response)
+                .chars()
+                .take(SHOWN_RESPONSE_SIZE)
+                .collect();
+            Err(Failure::Assertion(format!(
+                "response {:?} does not match /{}/",
+                shown,
+                re.as_str()
+            )))
+        }
+        _ => Err(Failure::Timeout),
+    }
+}
+
+/// ICMP port unreachable shows up as a refused connection on the next call
+fn unreachable(e: io::Error) -> Failure {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused => Failure::Connect("port unreachable".to_owned()),
+        _ => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::decode_hex;
+    use std::thread;
+
+    /// Echoes every datagram back upper cased, except for `quiet`
+    fn serve_echo() -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                if &buf[..n] != b"quiet" {
+                    let _ = socket.send_to(&buf[..n].to_ascii_uppercase(), peer);
+                }
+            }
+        });
+        port
+    }
+
+    fn udp_check(port: u16, extra: &str) -> HealthCheck {
+        let check = format!("{{method: udp, port: {}{}}}", port, extra);
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn matches_response() {
+        let port = serve_echo();
+        let check = udp_check(port, ", payload: hello, banner: ^HELLO$");
+        assert!(super::check("127.0.0.1", &check).is_success());
+
+        // "griffin" in hex
+        let check = udp_check(port, ", payload_hex: 67 72 69 66 66 69 6e, banner: GRIFFIN");
+        assert!(super::check("127.0.0.1", &check).is_success());
+
+        let check = udp_check(port, ", payload: hello, banner: ^hello$");
+        let addr = SocketAddr::new("127.0.0.1".parse().unwrap(), port);
+        assert_eq!(
+            probe(addr, &check, Duration::from_millis(200)),
+            Err(Failure::Assertion(
+                "response \"HELLO\" does not match /^hello$/".to_owned()
+            ))
+        );
+
+        // binary responses are matched byte by byte
+        let check = udp_check(
+            port,
+            r#", payload_hex: ff 00 61, banner: '^(?-u:\xff)\x00A$'"#,
+        );
+        assert!(super::check("127.0.0.1", &check).is_success());
+    }
+
+    #[test]
+    fn times_out_without_response() {
+        let port = serve_echo();
+        let check = udp_check(port, ", payload: quiet");
+        let addr = SocketAddr::new("127.0.0.1".parse().unwrap(), port);
+        assert_eq!(
+            probe(addr, &check, Duration::from_millis(200)),
+            Err(Failure::Timeout)
+        );
+    }
+
+    #[test]
+    fn fails_on_closed_port() {
+        let port = {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.local_addr().unwrap().port()
+        };
+        let result = check("127.0.0.1", &udp_check(port, ", payload: hello"));
+        assert_eq!(
+            result.failure,
+            Some(Failure::Connect("port unreachable".to_owned()))
+        );
+    }
+
+    #[test]
+    fn decode_hex_payloads() {
+        assert_eq!(decode_hex("1b00 ff\n0A"), Ok(vec![0x1b, 0, 0xff, 0x0a]));
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+}
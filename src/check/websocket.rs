@@ -0,0 +1,417 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    time::Instant,
+};
+
+use ring::{digest, rand::SecureRandom};
+
+use super::{
+    connect,
+    http::{self, read_response, request_parts, ReadWrite, Response},
+    payload, timeout,
+    tls::connect_tls,
+    CheckResult, Failure, DEFAULT_TIMEOUT,
+};
+use crate::config::{HealthCheck, Scheme};
+
+/// Appended to the key of a handshake to compute the accept header, as set
+/// by RFC 6455
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message read from a host
+const MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+/// Longest part of a message shown when it doesn't match
+const SHOWN_MESSAGE_SIZE: usize = 100;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// Status code of a normal closure
+const CLOSE_NORMAL: u16 = 1000;
+
+/// A single WebSocket frame
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Upgrades a connection to `endpoint` of a host to a WebSocket. When the
+/// check has a payload it is sent as a message, and the first message that
+/// comes back must match the banner pattern when one is set.
+pub fn check(host: &str, check: &HealthCheck) -> CheckResult {
+    let start = Instant::now();
+    let timeout = timeout(check, DEFAULT_TIMEOUT);
+    let result = request_parts(check).and_then(|(headers, _)| {
+        let default_port = match check.scheme {
+            Scheme::Http => http::DEFAULT_PORT,
+            Scheme::Https => http::DEFAULT_TLS_PORT,
+        };
+        let port = check.port.unwrap_or(default_port);
+        let mut stream: Box<dyn ReadWrite> = match check.scheme {
+            Scheme::Http => Box::new(connect(host, port, timeout)?),
+            Scheme::Https => Box::new(connect_tls(host, port, &check.tls, timeout)?),
+        };
+        let authority = http::authority(host, port, default_port);
+        let path = check.endpoint.as_deref().unwrap_or("/");
+        let key = base64::encode(random::<16>()?);
+        stream.write_all(&handshake(&authority, path, &key, &headers))?;
+        stream.flush()?;
+
+        // frames may follow the handshake response right away, so all
+        // reading goes through the same buffer
+        let mut rdr = BufReader::new(stream);
+        let response = read_response(&mut rdr, false)?;
+        accept(&response, &key)?;
+        exchange(&mut rdr, check)?;
+        let _ = rdr
+            .get_mut()
+            .write_all(&frame(OP_CLOSE, &CLOSE_NORMAL.to_be_bytes())?);
+        Ok(())
+    });
+    match result {
+        Ok(()) => CheckResult::success(start.elapsed()),
+        Err(failure) => CheckResult::failure(start.elapsed(), failure),
+    }
+}
+
+/// The upgrade request. Validation keeps the headers of the check from
+/// overriding the ones the handshake needs.
+fn handshake(authority: &str, path: &str, key: &str, headers: &http::Headers) -> Vec<u8> {
+    let mut head = format!(
+        "GET {} HTTP/1.1\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n\
+         Sec-WebSocket-Version: 13\r\n",
+        path, key
+    );
+    let user_agent = format!("griffin/{}", env!("CARGO_PKG_VERSION"));
+    let defaults = [("Host", authority), ("User-Agent", user_agent.as_str())];
+    http::push_headers(&mut head, &defaults, headers);
+    head.push_str("\r\n");
+    head.into_bytes()
+}
+
+/// Makes sure the host switched protocols and knows what a WebSocket is
+fn accept(response: &Response, key: &str) -> Result<(), Failure> {
+    if response.status != 101 {
+        return Err(Failure::Status(response.status));
+    }
+    let upgrade = response.header("upgrade").unwrap_or_default();
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return Err(Failure::Protocol(format!(
+            "upgraded to {:?} instead of websocket",
+            upgrade
+        )));
+    }
+    let hash = digest::digest(
+        &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{}{}", key, ACCEPT_GUID).as_bytes(),
+    );
+    if response.header("sec-websocket-accept") != Some(&base64::encode(hash)) {
+        return Err(Failure::Protocol(
+            "handshake has a wrong Sec-WebSocket-Accept".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Sends the payload of a check and waits for a reply, which must match the
+/// banner when one is set
+fn exchange<S: Read + Write>(rdr: &mut BufReader<S>, check: &HealthCheck) -> Result<(), Failure> {
+    let payload = payload(check)?;
+    if !payload.is_empty() {
+        let opcode = if check.payload_hex.is_some() {
+            OP_BINARY
+        } else {
+            OP_TEXT
+        };
+        rdr.get_mut().write_all(&frame(opcode, &payload)?)?;
+    }
+    if payload.is_empty() && check.banner.is_none() {
+        return Ok(());
+    }
+    let message = read_message(rdr)?;
+    match &check.banner {
+        Some(re) if !re.is_match(&message) => {
+            let shown: String = String::from_utf8_lossy(&message)
+                .chars()
+                .take(SHOWN_MESSAGE_SIZE)
+                .collect();
+            Err(Failure::Assertion(format!(
+                "message {:?} does not match /{}/",
+                shown,
+                re.as_str()
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the next data message, joining fragments and answering pings
+fn read_message<S: Read + Write>(rdr: &mut BufReader<S>) -> Result<Vec<u8>, Failure> {
+    let mut message = Vec::new();
+    loop {
+        let frame = read_frame(rdr)?;
+        match frame.opcode {
+            OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                message.extend(frame.payload);
+                if message.len() as u64 > MAX_MESSAGE_SIZE {
+                    return Err(Failure::Protocol("message is too large".to_owned()));
+                }
+                if frame.fin {
+                    return Ok(message);
+                }
+            }
+            OP_PING => rdr
+                .get_mut()
+                .write_all(&self::frame(OP_PONG, &frame.payload)?)?,
+            OP_CLOSE => {
+                let reason = match frame.payload.get(..2) {
+                    Some(code) => format!(" with code {}", u16::from_be_bytes([code[0], code[1]])),
+                    None => String::new(),
+                };
+                return Err(Failure::Protocol(format!(
+                    "connection closed by the host{}",
+                    reason
+                )));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Serializes a frame the way a client must, masked
+fn frame(opcode: u8, payload: &[u8]) -> Result<Vec<u8>, Failure> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xffff => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    let mask = random::<4>()?;
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    Ok(frame)
+}
+
+fn read_frame<R: BufRead>(rdr: &mut R) -> Result<Frame, Failure> {
+    let mut head = [0; 2];
+    rdr.read_exact(&mut head)?;
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            rdr.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            rdr.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Failure::Protocol(format!(
+            "frame of {} bytes is too large",
+            len
+        )));
+    }
+    // servers don't mask frames, but unmasking is cheap
+    let mut mask = [0; 4];
+    let masked = head[1] & 0x80 != 0;
+    if masked {
+        rdr.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    rdr.read_exact(&mut payload)?;
+    if masked {
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0f,
+        payload,
+    })
+}
+
+fn random<const N: usize>() -> Result<[u8; N], Failure> {
+    let mut bytes = [0; N];
+    ring::rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Failure::Io("could not generate random bytes".to_owned()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{net::TcpListener, thread, time::Duration};
+
+    /// Stands in for a realtime gateway. Upgrades on /ws, echoing messages
+    /// back upper cased in two fragments after a ping, and answers any other
+    /// path with a plain HTTP response.
+    fn serve_gateway() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = answer(stream.unwrap());
+            }
+        });
+        port
+    }
+
+    fn answer<S: Read + Write>(stream: S) -> Result<(), Failure> {
+        let mut rdr = BufReader::new(stream);
+        let mut request = Vec::new();
+        loop {
+            let mut line = String::new();
+            rdr.read_line(&mut line)?;
+            if line == "\r\n" {
+                break;
+            }
+            request.push(line.trim_end().to_owned());
+        }
+        let key = request
+            .iter()
+            .find_map(|l| l.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap_or_default();
+        let stream = rdr.get_mut();
+        if !request[0].starts_with("GET /ws ") {
+            let ok = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+            return Ok(stream.write_all(ok.as_bytes())?);
+        }
+        let hash = digest::digest(
+            &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            format!("{}{}", key, ACCEPT_GUID).as_bytes(),
+        );
+        let handshake = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            base64::encode(hash)
+        );
+        let mut msg = handshake.into_bytes();
+        // unmasked frames, like servers send
+        msg.extend_from_slice(&[0x80 | OP_PING, 2, b'h', b'i']);
+        stream.write_all(&msg)?;
+
+        loop {
+            let frame = read_frame(&mut rdr)?;
+            let stream = rdr.get_mut();
+            match frame.opcode {
+                OP_TEXT => {
+                    let upper = frame.payload.to_ascii_uppercase();
+                    let (first, rest) = upper.split_at(upper.len() / 2);
+                    let mut msg = vec![OP_TEXT, first.len() as u8];
+                    msg.extend_from_slice(first);
+                    msg.extend_from_slice(&[0x80 | OP_CONTINUATION, rest.len() as u8]);
+                    msg.extend_from_slice(rest);
+                    stream.write_all(&msg)?;
+                }
+                OP_PONG => assert_eq!(frame.payload, b"hi"),
+                OP_CLOSE => {
+                    stream.write_all(&[0x80 | OP_CLOSE, 2, 0x03, 0xe8])?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn websocket_check(port: u16, extra: &str) -> HealthCheck {
+        let check = format!("{{method: websocket, port: {}{}}}", port, extra);
+        serde_yaml::from_str(&check).unwrap()
+    }
+
+    #[test]
+    fn upgrades_and_echoes() {
+        let port = serve_gateway();
+        let check = websocket_check(port, ", endpoint: /ws");
+        let result = super::check("127.0.0.1", &check);
+        assert!(result.is_success(), "{:?}", result.failure);
+
+        let check = websocket_check(port, ", endpoint: /ws, payload: hello, banner: ^HELLO$");
+        let result = super::check("127.0.0.1", &check);
+        assert!(result.is_success(), "{:?}", result.failure);
+
+        let check = websocket_check(port, ", endpoint: /ws, payload: hello, banner: ^hello$");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Assertion(
+                "message \"HELLO\" does not match /^hello$/".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn fails_when_upgrade_is_refused() {
+        let port = serve_gateway();
+        let check = websocket_check(port, ", endpoint: /other");
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Status(200))
+        );
+    }
+
+    #[test]
+    fn times_out_waiting_for_reply() {
+        let port = serve_gateway();
+        // binary messages aren't echoed
+        let check = websocket_check(
+            port,
+            ", endpoint: /ws, payload_hex: 00ff, banner: ., timeout: 200ms",
+        );
+        let start = Instant::now();
+        assert_eq!(
+            super::check("127.0.0.1", &check).failure,
+            Some(Failure::Timeout)
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn headers_override_defaults() {
+        let headers = vec![
+            ("host".to_owned(), "api.example.com".to_owned()),
+            ("X-Token".to_owned(), "abc".to_owned()),
+        ];
+        let head = handshake("127.0.0.1:8080", "/ws", "a2V5", &headers);
+        let head = String::from_utf8(head).unwrap();
+        assert!(!head.contains("Host: 127.0.0.1:8080"));
+        assert!(head.contains("\r\nhost: api.example.com\r\n"));
+        assert!(head.contains("\r\nX-Token: abc\r\n"));
+        assert!(head.contains("\r\nUser-Agent: griffin/"));
+        assert!(head.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn frames_are_masked() {
+        let frame = frame(OP_TEXT, b"hello").unwrap();
+        assert_eq!(frame[..2], [0x81, 0x85]);
+        let parsed = read_frame(&mut &frame[..]).unwrap();
+        assert!(parsed.fin);
+        assert_eq!(parsed.payload, b"hello");
+
+        let frame = self::frame(OP_BINARY, &[0; 300]).unwrap();
+        assert_eq!(frame[1..4], [0x80 | 126, 1, 44]);
+    }
+}
@@ -0,0 +1,1211 @@
+use regex::{bytes, Regex, RegexBuilder};
+use serde::{de::Error, Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    io::{BufReader, Read},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+mod secret;
+mod validate;
+
+pub use secret::{Secret, SecretError};
+pub use validate::{Problem, Severity, MIN_INTERVAL};
+
+#[derive(Debug, PartialEq)]
+/// TimeUnit represents time duration's unit in days, hours, minutes, seconds, milliseconds
+pub enum TimeUnit {
+    Days,
+    Hours,
+    Minutes,
+    Seconds,
+    Milliseconds,
+}
+
+#[derive(Debug, Clone)]
+/// Error when time unit is not valid
+pub struct TimeUnitError {
+    str: String,
+}
+
+impl Display for TimeUnitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid duration {}", self.str)
+    }
+}
+
+impl FromStr for TimeUnit {
+    type Err = TimeUnitError;
+
+    /// Convert a string to TimeUnit
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "ms" => Ok(TimeUnit::Milliseconds),
+            "s" => Ok(TimeUnit::Seconds),
+            "min" => Ok(TimeUnit::Minutes),
+            "h" => Ok(TimeUnit::Hours),
+            "d" => Ok(TimeUnit::Days),
+            _ => Err(TimeUnitError { str: s.to_owned() }),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// Represents a time interval with a value and an unit
+pub struct Interval {
+    pub value: u32,
+    pub unit: TimeUnit,
+}
+
+impl Interval {
+    /// Creates a new Interval from a value and TimeUnit
+    pub fn new(value: u32, unit: TimeUnit) -> Self {
+        Self { value, unit }
+    }
+
+    /// Converts the Interval to a Duration
+    pub fn as_duration(&self) -> Duration {
+        let value = u64::from(self.value);
+        match self.unit {
+            TimeUnit::Days => Duration::from_secs(value * 24 * 60 * 60),
+            TimeUnit::Hours => Duration::from_secs(value * 60 * 60),
+            TimeUnit::Minutes => Duration::from_secs(value * 60),
+            TimeUnit::Seconds => Duration::from_secs(value),
+            TimeUnit::Milliseconds => Duration::from_millis(value),
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.unit {
+            TimeUnit::Days => "d",
+            TimeUnit::Hours => "h",
+            TimeUnit::Minutes => "min",
+            TimeUnit::Seconds => "s",
+            TimeUnit::Milliseconds => "ms",
+        };
+        write!(f, "{}{}", self.value, unit)
+    }
+}
+
+impl Default for Interval {
+    /// Default time interval is 30s
+    fn default() -> Self {
+        Interval {
+            value: 30,
+            unit: TimeUnit::Seconds,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// Health check method used
+pub enum HealthCheckMethod {
+    #[serde(rename = "http")]
+    Http,
+    #[serde(rename = "ping")]
+    Ping,
+    #[serde(rename = "tls_cert")]
+    TlsCert,
+    #[serde(rename = "tcp")]
+    Tcp,
+    #[serde(rename = "dns")]
+    Dns,
+    #[serde(rename = "udp")]
+    Udp,
+    #[serde(rename = "exec")]
+    Exec,
+    #[serde(rename = "grpc")]
+    Grpc,
+    #[serde(rename = "postgres")]
+    Postgres,
+    #[serde(rename = "mysql")]
+    Mysql,
+    #[serde(rename = "redis")]
+    Redis,
+    #[serde(rename = "websocket")]
+    WebSocket,
+}
+
+impl Display for HealthCheckMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthCheckMethod::Http => write!(f, "http"),
+            HealthCheckMethod::Ping => write!(f, "ping"),
+            HealthCheckMethod::TlsCert => write!(f, "tls_cert"),
+            HealthCheckMethod::Tcp => write!(f, "tcp"),
+            HealthCheckMethod::Dns => write!(f, "dns"),
+            HealthCheckMethod::Udp => write!(f, "udp"),
+            HealthCheckMethod::Exec => write!(f, "exec"),
+            HealthCheckMethod::Grpc => write!(f, "grpc"),
+            HealthCheckMethod::Postgres => write!(f, "postgres"),
+            HealthCheckMethod::Mysql => write!(f, "mysql"),
+            HealthCheckMethod::Redis => write!(f, "redis"),
+            HealthCheckMethod::WebSocket => write!(f, "websocket"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+/// HTTP request method used by http checks
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+}
+
+impl Display for HttpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        let method = match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Options => "OPTIONS",
+        };
+        f.write_str(method)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Protocol used by http, grpc and websocket checks
+pub enum Scheme {
+    #[default]
+    Http,
+    Https,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// How TLS connections are set up and verified
+pub struct TlsOptions {
+    /// PEM bundle of CA certificates trusted instead of the built in roots
+    pub ca_file: Option<PathBuf>,
+    /// PEM certificate chain presented to the host for mutual TLS
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key of `client_cert`
+    pub client_key: Option<PathBuf>,
+    /// Name sent with SNI and verified against the certificate instead of the host
+    pub server_name: Option<String>,
+    /// Accept any certificate. Only meant for testing.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsOptions {
+    /// Whether no option is set
+    pub fn is_empty(&self) -> bool {
+        self.ca_file.is_none()
+            && self.client_cert.is_none()
+            && self.client_key.is_none()
+            && self.server_name.is_none()
+            && !self.insecure_skip_verify
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// How close to expiry a certificate may get before tls_cert checks complain
+pub struct CertExpiry {
+    #[serde(default = "default_expiry_warning")]
+    #[serde(deserialize_with = "interval_from_str")]
+    pub warning: Interval,
+    #[serde(default = "default_expiry_critical")]
+    #[serde(deserialize_with = "interval_from_str")]
+    pub critical: Interval,
+}
+
+fn default_expiry_warning() -> Interval {
+    Interval::new(21, TimeUnit::Days)
+}
+
+fn default_expiry_critical() -> Interval {
+    Interval::new(7, TimeUnit::Days)
+}
+
+impl Default for CertExpiry {
+    fn default() -> Self {
+        Self {
+            warning: default_expiry_warning(),
+            critical: default_expiry_critical(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+/// Type of DNS record looked up by dns checks
+pub enum RecordType {
+    #[default]
+    A,
+    Aaaa,
+    Cname,
+    Mx,
+    Txt,
+}
+
+impl Display for RecordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Cname => "CNAME",
+            RecordType::Mx => "MX",
+            RecordType::Txt => "TXT",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// What dns checks look up and expect
+pub struct DnsOptions {
+    /// Name looked up instead of the host
+    pub name: Option<String>,
+    /// Address of the DNS server, like `10.0.0.2` or `127.0.0.1:5353`.
+    /// The first nameserver of /etc/resolv.conf is used when not set.
+    pub resolver: Option<String>,
+    #[serde(default)]
+    pub record_type: RecordType,
+    /// Values that must be among the records, like `10 mail.example.com` for MX
+    #[serde(default)]
+    pub expect: Vec<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_interval_from_str")]
+    pub max_resolution_time: Option<Interval>,
+}
+
+impl DnsOptions {
+    /// Whether no option is set
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.resolver.is_none()
+            && self.record_type == RecordType::A
+            && self.expect.is_empty()
+            && self.max_resolution_time.is_none()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Login options of postgres, mysql and redis checks
+pub struct DatabaseOptions {
+    /// User to log in as, the default user of redis when not set
+    pub user: Option<Secret>,
+    pub password: Option<Secret>,
+    /// Database to connect to, which is an index for redis
+    pub name: Option<String>,
+}
+
+impl DatabaseOptions {
+    /// Whether no option is set
+    pub fn is_empty(&self) -> bool {
+        self.user.is_none() && self.password.is_none() && self.name.is_none()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Credentials for HTTP basic authentication
+pub struct BasicAuth {
+    pub username: Secret,
+    pub password: Secret,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Inclusive range of HTTP status codes, written as `200`, `200-299` or `2xx`
+pub struct StatusRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl StatusRange {
+    /// Whether a status code is in the range
+    pub fn contains(&self, code: u16) -> bool {
+        self.start <= code && code <= self.end
+    }
+}
+
+impl Display for StatusRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl FromStr for StatusRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("{} is invalid status code range", s);
+        let s = s.trim();
+        let (start, end) = if let Some(class) = s.strip_suffix("xx") {
+            let class = class.parse::<u16>().map_err(|_| err())?;
+            (class * 100, class * 100 + 99)
+        } else if let Some((start, end)) = s.split_once('-') {
+            (
+                start.trim().parse().map_err(|_| err())?,
+                end.trim().parse().map_err(|_| err())?,
+            )
+        } else {
+            let code = s.parse().map_err(|_| err())?;
+            (code, code)
+        };
+        if !(100..=599).contains(&start) || !(100..=599).contains(&end) || start > end {
+            return Err(err());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Code(u16),
+            Range(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Code(code) => code.to_string().parse(),
+            Raw::Range(range) => range.parse(),
+        }
+        .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Assertions a HTTP response must pass
+pub struct HttpExpect {
+    /// Accepted status codes, any 2xx when empty
+    #[serde(default)]
+    pub status: Vec<StatusRange>,
+    /// Text the body must contain
+    pub body: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_regex_from_str")]
+    pub body_regex: Option<Regex>,
+    /// Headers that must be present. When a value is given the header must contain it.
+    #[serde(default)]
+    pub headers: BTreeMap<String, Option<String>>,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_interval_from_str")]
+    pub max_response_time: Option<Interval>,
+}
+
+impl HttpExpect {
+    /// Whether no assertion is set
+    pub fn is_empty(&self) -> bool {
+        self.status.is_empty()
+            && self.body.is_none()
+            && self.body_regex.is_none()
+            && self.headers.is_empty()
+            && self.max_response_time.is_none()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Health check details
+pub struct HealthCheck {
+    pub method: HealthCheckMethod,
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "interval_from_str")]
+    pub interval: Interval,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub scheme: Scheme,
+    #[serde(default)]
+    pub tls: TlsOptions,
+    #[serde(default)]
+    pub http_method: HttpMethod,
+    /// Extra request headers of http and websocket checks
+    #[serde(default)]
+    pub headers: BTreeMap<String, Secret>,
+    /// Request body of http checks
+    pub body: Option<Secret>,
+    pub basic_auth: Option<BasicAuth>,
+    pub bearer_token: Option<Secret>,
+    #[serde(default)]
+    pub expect: HttpExpect,
+    /// Certificate expiry thresholds of tls_cert checks
+    #[serde(default)]
+    pub expiry: CertExpiry,
+    /// Data sent by tcp, udp and websocket checks
+    pub payload: Option<String>,
+    /// Binary data sent by tcp, udp and websocket checks, written as hex digits
+    pub payload_hex: Option<String>,
+    /// Pattern the raw data a tcp, udp or websocket check receives must
+    /// match, where `(?-u:\xff)` matches a single byte
+    #[serde(default)]
+    #[serde(deserialize_with = "option_bytes_regex_from_str")]
+    pub banner: Option<bytes::Regex>,
+    #[serde(default)]
+    pub dns: DnsOptions,
+    /// Program run by exec checks, looked up in PATH unless it is a path
+    pub command: Option<String>,
+    /// Arguments passed to the command of exec checks
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Time each probe of a check may take before it fails. Exec checks kill
+    /// their command once it runs this long.
+    #[serde(default)]
+    #[serde(deserialize_with = "option_interval_from_str")]
+    pub timeout: Option<Interval>,
+    /// Extra attempts made when a run fails before its result counts
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default = "default_retry_interval")]
+    #[serde(deserialize_with = "interval_from_str")]
+    pub retry_interval: Interval,
+    /// Consecutive failed runs before the check counts as failing
+    #[serde(default = "default_threshold")]
+    pub failure_threshold: u32,
+    /// Consecutive passed runs before the check counts as passing again
+    #[serde(default = "default_threshold")]
+    pub success_threshold: u32,
+    /// Share of the check in the status of a service with a weighted policy
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Service asked about by grpc checks, the whole server when not set
+    pub grpc_service: Option<String>,
+    #[serde(default)]
+    pub database: DatabaseOptions,
+}
+
+fn default_retry_interval() -> Interval {
+    Interval::new(1, TimeUnit::Seconds)
+}
+
+fn default_threshold() -> u32 {
+    1
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl HealthCheck {
+    /// Every secret of the check, along with the key it is set at
+    pub fn secrets(&self) -> Vec<(String, &Secret)> {
+        let mut secrets: Vec<(String, &Secret)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (format!("headers.{}", name), value))
+            .collect();
+        if let Some(body) = &self.body {
+            secrets.push(("body".to_owned(), body));
+        }
+        if let Some(auth) = &self.basic_auth {
+            secrets.push(("basic_auth.username".to_owned(), &auth.username));
+            secrets.push(("basic_auth.password".to_owned(), &auth.password));
+        }
+        if let Some(token) = &self.bearer_token {
+            secrets.push(("bearer_token".to_owned(), token));
+        }
+        if let Some(user) = &self.database.user {
+            secrets.push(("database.user".to_owned(), user));
+        }
+        if let Some(password) = &self.database.password {
+            secrets.push(("database.password".to_owned(), password));
+        }
+        secrets
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// How the checks of a service add up to its status. A service that isn't
+/// down but has failing checks is degraded.
+pub enum StatusPolicy {
+    /// Down when any check fails
+    #[default]
+    All,
+    /// Down only when every check fails
+    Any,
+    /// Down when fewer than this many checks pass
+    Quorum(u32),
+    /// Down when the passing checks have less than this fraction of the total
+    /// weight of the checks
+    Weighted(f64),
+}
+
+impl Display for StatusPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusPolicy::All => write!(f, "all"),
+            StatusPolicy::Any => write!(f, "any"),
+            StatusPolicy::Quorum(n) => write!(f, "quorum of {}", n),
+            StatusPolicy::Weighted(fraction) => write!(f, "weighted {}", fraction),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Whether a service is shown on the status page
+pub enum Visibility {
+    #[default]
+    Public,
+    Private,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Assigned backend
+pub struct Service {
+    pub name: String,
+    pub host: String,
+    #[serde(default)]
+    pub policy: StatusPolicy,
+    /// Heading the service is listed under on the status page
+    pub group: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    pub health: Vec<HealthCheck>,
+}
+
+fn default_smtp_host() -> String {
+    "localhost".to_owned()
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+/// Where to send a notice when a service goes up or down
+pub enum Alert {
+    Email {
+        from: String,
+        to: String,
+        #[serde(default = "default_smtp_host")]
+        smtp_host: String,
+        #[serde(default = "default_smtp_port")]
+        smtp_port: u16,
+    },
+    Webhook {
+        url: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Embedded HTTP server for the status page, API and Prometheus metrics
+pub struct ServerOptions {
+    /// Address to listen on, like 127.0.0.1:8080
+    pub listen: SocketAddr,
+    /// Title of the status page
+    #[serde(default = "default_title")]
+    pub title: String,
+}
+
+fn default_title() -> String {
+    "Service status".to_owned()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Where check results are kept across restarts
+pub struct HistoryOptions {
+    /// File results are appended to, one JSON object per line
+    pub path: PathBuf,
+    /// How long results are kept
+    #[serde(default = "default_retention", deserialize_with = "interval_from_str")]
+    pub retention: Interval,
+}
+
+fn default_retention() -> Interval {
+    Interval::new(30, TimeUnit::Days)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Configuration
+pub struct Config {
+    pub services: Vec<Service>,
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+    /// Serves the status page, API and metrics when set
+    pub server: Option<ServerOptions>,
+    /// Records every check result when set
+    pub history: Option<HistoryOptions>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Serde(serde_yaml::Error),
+    /// A key that is not part of the configuration, usually a typo
+    UnknownKey {
+        key: String,
+        line: usize,
+        column: usize,
+    },
+    /// Problems found by validation, at least one of them an error
+    Invalid(Vec<Problem>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Serde(e) => write!(f, "invalid config: {}", e),
+            ConfigError::UnknownKey { key, line, column } => write!(
+                f,
+                "unknown key `{}` at line {} column {}",
+                key, line, column
+            ),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid config:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<serde_yaml::Error> for ConfigError {
+    fn from(v: serde_yaml::Error) -> Self {
+        let msg = v.to_string();
+        match (UNKNOWN_FIELD_RE.captures(&msg), v.location()) {
+            (Some(captures), Some(location)) => ConfigError::UnknownKey {
+                key: captures[1].to_owned(),
+                line: location.line(),
+                column: location.column(),
+            },
+            _ => ConfigError::Serde(v),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(v: std::io::Error) -> Self {
+        ConfigError::Io(v)
+    }
+}
+
+lazy_static! {
+    /// Regex expression to match time durations in string format
+    static ref RE: Regex = RegexBuilder::new(r"^(\d+)(d|h|min|s|ms)$")
+        .case_insensitive(true)
+        .build()
+        .unwrap();
+
+    /// Regex expression to find the key in serde's unknown field errors
+    static ref UNKNOWN_FIELD_RE: Regex = Regex::new(r"unknown field `([^`]*)`").unwrap();
+}
+
+/// Get Interval from serde
+fn interval_from_str<'de, D>(deserializer: D) -> Result<Interval, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let str = String::deserialize(deserializer)?;
+    match RE.captures(&str) {
+        Some(captures) => {
+            let val = match captures.get(1) {
+                Some(v) => v.as_str().parse::<u32>().unwrap(),
+                None => 0,
+            };
+            if val == 0 {
+                return Err(D::Error::custom("duration must be 0 or greater"));
+            }
+            let dur = captures.get(2).map_or("", |m| m.as_str());
+            let tu = TimeUnit::from_str(dur).map_err(|e| D::Error::custom(e.to_string()))?;
+            Ok(Interval::new(val, tu))
+        }
+        None => Err(D::Error::custom(format!("{} is invalid duration", &str))),
+    }
+}
+
+/// Get an optional Interval from serde
+fn option_interval_from_str<'de, D>(deserializer: D) -> Result<Option<Interval>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    interval_from_str(deserializer).map(Some)
+}
+
+/// Get an optional Regex from serde
+fn option_regex_from_str<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let str = String::deserialize(deserializer)?;
+    Regex::new(&str).map(Some).map_err(D::Error::custom)
+}
+
+/// Get an optional Regex matching bytes from serde
+fn option_bytes_regex_from_str<'de, D>(deserializer: D) -> Result<Option<bytes::Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let str = String::deserialize(deserializer)?;
+    bytes::Regex::new(&str).map(Some).map_err(D::Error::custom)
+}
+
+impl Config {
+    /// creates a new config from a file
+    pub fn new_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let file = fs::File::open(path)?;
+        let reader = BufReader::new(file);
+        Self::new(reader)
+    }
+
+    /// creates a new config from a reader and validates it.
+    ///
+    /// Only errors fail the config, warnings can be found with `validate`.
+    pub fn new<R: Read>(rdr: R) -> Result<Self, ConfigError> {
+        let config: Config = serde_yaml::from_reader(rdr)?;
+        let problems = config.validate();
+        if problems.iter().any(|p| p.severity == Severity::Error) {
+            return Err(ConfigError::Invalid(problems));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_correct_config() {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: http
+                  endpoint: /status
+                  port: 4040
+                  interval: 1h
+                - method: ping
+        "###;
+
+        let conf = Config::new(config.as_bytes()).unwrap();
+        assert_eq!(conf.services.len(), 1);
+        assert_eq!(
+            conf.services[0].health[0].interval,
+            Interval::new(1, TimeUnit::Hours)
+        );
+    }
+
+    #[test]
+    fn fail_on_unknown_key() {
+        let config = "services:
+- name: Foo Web Service
+  host: foo.example.com
+  health:
+    - method: http
+      intreval: 1h
+";
+
+        match Config::new(config.as_bytes()) {
+            Err(ConfigError::UnknownKey { key, line, column }) => {
+                assert_eq!(key, "intreval");
+                assert_eq!((line, column), (6, 7));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_alerts() {
+        let config = r###"
+            services: []
+            alerts:
+              - type: Email
+                from: griffin@foo.com
+                to: webmaster@foo.com
+              - type: Webhook
+                url: https://mywebhook.com/abcd13345
+        "###;
+
+        let conf = Config::new(config.as_bytes()).unwrap();
+        match &conf.alerts[0] {
+            Alert::Email {
+                to,
+                smtp_host,
+                smtp_port,
+                ..
+            } => {
+                assert_eq!(to, "webmaster@foo.com");
+                assert_eq!((smtp_host.as_str(), *smtp_port), ("localhost", 25));
+            }
+            other => panic!("unexpected alert {:?}", other),
+        }
+        match &conf.alerts[1] {
+            Alert::Webhook { url } => assert_eq!(url, "https://mywebhook.com/abcd13345"),
+            other => panic!("unexpected alert {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_server_options() {
+        let conf = Config::new("services: []".as_bytes()).unwrap();
+        assert!(conf.server.is_none());
+
+        let config = "services: []\nserver:\n  listen: 127.0.0.1:8080\n";
+        let server = Config::new(config.as_bytes()).unwrap().server.unwrap();
+        assert_eq!(server.listen, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(server.title, "Service status");
+
+        let config = "services: []\nserver:\n  listen: localhost\n";
+        assert!(Config::new(config.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_history_options() {
+        let config = "services: []\nhistory:\n  path: /var/lib/griffin/results.jsonl\n";
+        let history = Config::new(config.as_bytes()).unwrap().history.unwrap();
+        assert_eq!(history.path, Path::new("/var/lib/griffin/results.jsonl"));
+        assert_eq!(history.retention, Interval::new(30, TimeUnit::Days));
+
+        let config = "services: []\nhistory: {path: results.jsonl, retention: 7d}\n";
+        let history = Config::new(config.as_bytes()).unwrap().history.unwrap();
+        assert_eq!(history.retention, Interval::new(7, TimeUnit::Days));
+    }
+
+    #[test]
+    fn fail_on_unknown_alert_key() {
+        let config = "services: []
+alerts:
+  - type: Webhook
+    uri: https://mywebhook.com/abcd13345
+";
+
+        match Config::new(config.as_bytes()) {
+            // alerts are buffered to read their type, so the whole alert is reported
+            Err(ConfigError::UnknownKey { key, line, .. }) => {
+                assert_eq!((key.as_str(), line), ("uri", 3));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_sample_config() {
+        assert!(Config::new_from_file("griffin.yaml").is_ok());
+    }
+
+    #[test]
+    fn parse_http_expectations() {
+        let check = r###"
+            method: http
+            endpoint: /status
+            expect:
+              status: [200, "300-302", 4xx]
+              body_regex: "^ok$"
+              headers:
+                Content-Type: json
+                X-Request-Id:
+              max_response_time: 500ms
+        "###;
+
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        let expect = &check.expect;
+        assert_eq!(
+            expect.status,
+            vec![
+                StatusRange {
+                    start: 200,
+                    end: 200
+                },
+                StatusRange {
+                    start: 300,
+                    end: 302
+                },
+                StatusRange {
+                    start: 400,
+                    end: 499
+                },
+            ]
+        );
+        assert!(expect.body_regex.as_ref().unwrap().is_match("ok"));
+        assert_eq!(expect.headers["Content-Type"].as_deref(), Some("json"));
+        assert_eq!(expect.headers["X-Request-Id"], None);
+        assert_eq!(
+            expect.max_response_time,
+            Some(Interval::new(500, TimeUnit::Milliseconds))
+        );
+
+        assert!("600".parse::<StatusRange>().is_err());
+        assert!("299-200".parse::<StatusRange>().is_err());
+    }
+
+    #[test]
+    fn parse_http_request_options() {
+        let check = r###"
+            method: http
+            endpoint: /api/health
+            http_method: POST
+            headers:
+              X-Api-Key: ${API_KEY}
+            body: '{"deep": true}'
+            basic_auth:
+              username: monitor
+              password: ${MONITOR_PASSWORD}
+        "###;
+
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.http_method, HttpMethod::Post);
+        assert_eq!(check.headers["X-Api-Key"], Secret::new("${API_KEY}"));
+        assert_eq!(check.body, Some(Secret::new(r#"{"deep": true}"#)));
+        let keys: Vec<String> = check.secrets().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "headers.X-Api-Key",
+                "body",
+                "basic_auth.username",
+                "basic_auth.password"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_tcp_check() {
+        let check = r###"
+            method: tcp
+            port: 6379
+            payload: "PING\r\n"
+            banner: ^\+PONG
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.method.to_string(), "tcp");
+        assert_eq!(check.payload.as_deref(), Some("PING\r\n"));
+        assert!(check.banner.unwrap().is_match(b"+PONG\r\n"));
+    }
+
+    #[test]
+    fn parse_exec_check() {
+        let check = r###"
+            method: exec
+            command: /usr/lib/nagios/plugins/check_disk
+            args: [-w, 20%, -c, 10%, -p, /]
+            timeout: 30s
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.method.to_string(), "exec");
+        assert_eq!(
+            check.command.as_deref(),
+            Some("/usr/lib/nagios/plugins/check_disk")
+        );
+        assert_eq!(check.args, vec!["-w", "20%", "-c", "10%", "-p", "/"]);
+        assert_eq!(check.timeout, Some(Interval::new(30, TimeUnit::Seconds)));
+    }
+
+    #[test]
+    fn parse_grpc_check() {
+        let check = r###"
+            method: grpc
+            port: 50051
+            scheme: https
+            grpc_service: orders.v1.Orders
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.method.to_string(), "grpc");
+        assert_eq!(check.scheme, Scheme::Https);
+        assert_eq!(check.grpc_service.as_deref(), Some("orders.v1.Orders"));
+    }
+
+    #[test]
+    fn parse_database_check() {
+        let check = r###"
+            method: postgres
+            database:
+              user: griffin
+              password: ${PG_PASSWORD}
+              name: orders
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.method.to_string(), "postgres");
+        assert_eq!(check.database.user, Some(Secret::new("griffin")));
+        assert_eq!(check.database.name.as_deref(), Some("orders"));
+        let keys: Vec<String> = check.secrets().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["database.user", "database.password"]);
+    }
+
+    #[test]
+    fn parse_websocket_check() {
+        let check = r###"
+            method: websocket
+            endpoint: /realtime
+            scheme: https
+            payload: '{"type": "ping"}'
+            banner: '"type": ?"pong"'
+            timeout: 2s
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.method.to_string(), "websocket");
+        assert_eq!(check.endpoint.as_deref(), Some("/realtime"));
+        assert_eq!(check.payload.as_deref(), Some(r#"{"type": "ping"}"#));
+        assert!(check.banner.unwrap().is_match(br#"{"type":"pong"}"#));
+    }
+
+    #[test]
+    fn parse_retries_and_thresholds() {
+        let check = r###"
+            method: ping
+            timeout: 500ms
+            retries: 2
+            retry_interval: 3s
+            failure_threshold: 3
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(
+            check.timeout,
+            Some(Interval::new(500, TimeUnit::Milliseconds))
+        );
+        assert_eq!(check.retries, 2);
+        assert_eq!(check.retry_interval, Interval::new(3, TimeUnit::Seconds));
+        assert_eq!(check.failure_threshold, 3);
+        assert_eq!(check.success_threshold, 1);
+
+        let check: HealthCheck = serde_yaml::from_str("method: ping").unwrap();
+        assert_eq!(check.retries, 0);
+        assert_eq!(check.retry_interval, Interval::new(1, TimeUnit::Seconds));
+        assert_eq!(check.failure_threshold, 1);
+    }
+
+    #[test]
+    fn parse_status_policies() {
+        let service = r###"
+            name: Foo
+            host: foo.example.com
+            health: []
+        "###;
+        let service: Service = serde_yaml::from_str(service).unwrap();
+        assert_eq!(service.policy, StatusPolicy::All);
+
+        for (policy, expected) in &[
+            ("any", StatusPolicy::Any),
+            ("{quorum: 2}", StatusPolicy::Quorum(2)),
+            ("{weighted: 0.5}", StatusPolicy::Weighted(0.5)),
+        ] {
+            let service = format!("{{name: Foo, host: foo, policy: {}, health: []}}", policy);
+            let service: Service = serde_yaml::from_str(&service).unwrap();
+            assert_eq!(service.policy, *expected);
+        }
+        let check: HealthCheck = serde_yaml::from_str("{method: ping, weight: 3}").unwrap();
+        assert_eq!(check.weight, 3);
+    }
+
+    #[test]
+    fn parse_status_page_options() {
+        let service: Service = serde_yaml::from_str("{name: Foo, host: foo, health: []}").unwrap();
+        assert_eq!(
+            (service.group, service.visibility),
+            (None, Visibility::Public)
+        );
+
+        let service = "{name: Foo, host: foo, group: Web, visibility: private, health: []}";
+        let service: Service = serde_yaml::from_str(service).unwrap();
+        assert_eq!(service.group.as_deref(), Some("Web"));
+        assert_eq!(service.visibility, Visibility::Private);
+    }
+
+    #[test]
+    fn parse_udp_check() {
+        let check = r###"
+            method: udp
+            port: 123
+            payload_hex: 1b 00 00 00
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.method.to_string(), "udp");
+        assert_eq!(check.payload_hex.as_deref(), Some("1b 00 00 00"));
+        assert!(check.banner.is_none());
+    }
+
+    #[test]
+    fn parse_dns_check() {
+        let check = r###"
+            method: dns
+            dns:
+              name: example.com
+              resolver: 127.0.0.1:5353
+              record_type: MX
+              expect: [10 mail.example.com]
+              max_resolution_time: 200ms
+        "###;
+        let check: HealthCheck = serde_yaml::from_str(check).unwrap();
+        assert_eq!(check.dns.record_type, RecordType::Mx);
+        assert_eq!(check.dns.expect, vec!["10 mail.example.com"]);
+        assert_eq!(
+            check.dns.max_resolution_time,
+            Some(Interval::new(200, TimeUnit::Milliseconds))
+        );
+
+        let check: HealthCheck = serde_yaml::from_str("method: dns").unwrap();
+        assert!(check.dns.is_empty());
+    }
+
+    #[test]
+    fn parse_cert_expiry() {
+        let check: HealthCheck =
+            serde_yaml::from_str("{method: tls_cert, expiry: {warning: 30d}}").unwrap();
+        assert_eq!(check.expiry.warning, Interval::new(30, TimeUnit::Days));
+        assert_eq!(check.expiry.critical, Interval::new(7, TimeUnit::Days));
+
+        let check: HealthCheck = serde_yaml::from_str("{method: tls_cert}").unwrap();
+        assert_eq!(check.expiry, CertExpiry::default());
+    }
+
+    #[test]
+    fn interval_as_duration() {
+        assert_eq!(
+            Interval::new(2, TimeUnit::Hours).as_duration(),
+            Duration::from_secs(7200)
+        );
+        assert_eq!(
+            Interval::new(7, TimeUnit::Days).as_duration(),
+            Duration::from_secs(7 * 24 * 3600)
+        );
+        assert_eq!(
+            Interval::new(30, TimeUnit::Milliseconds).as_duration(),
+            Duration::from_millis(30)
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_config() {
+        let config = r###"
+            backends:
+        "###;
+
+        assert!(matches!(
+            Config::new(config.as_bytes()),
+            Err(ConfigError::UnknownKey { ref key, .. }) if key == "backends"
+        ));
+    }
+}
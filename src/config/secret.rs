@@ -0,0 +1,86 @@
+use regex::{Captures, Regex};
+use serde::{Deserialize, Deserializer};
+use std::{
+    env,
+    fmt::{self, Display},
+};
+
+lazy_static! {
+    /// Regex expression to match `${VAR}` references to environment variables
+    static ref VAR_RE: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Error when a secret refers to an environment variable that is not set
+pub struct SecretError {
+    var: String,
+}
+
+impl Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "environment variable {} is not set", self.var)
+    }
+}
+
+#[derive(Clone, PartialEq)]
+/// A config value that may hold `${VAR}` references to environment variables,
+/// so credentials don't have to be written into the config file.
+///
+/// References are resolved every time the value is used.
+pub struct Secret(String);
+
+impl Secret {
+    /// Creates a secret from a template
+    pub fn new<S: Into<String>>(template: S) -> Self {
+        Secret(template.into())
+    }
+
+    /// Replaces every `${VAR}` with the value of the environment variable
+    pub fn resolve(&self) -> Result<String, SecretError> {
+        let mut missing = None;
+        let value = VAR_RE.replace_all(&self.0, |caps: &Captures| match env::var(&caps[1]) {
+            Ok(value) => value,
+            Err(_) => {
+                missing.get_or_insert_with(|| caps[1].to_owned());
+                String::new()
+            }
+        });
+        match missing {
+            Some(var) => Err(SecretError { var }),
+            None => Ok(value.into_owned()),
+        }
+    }
+}
+
+impl fmt::Debug for Secret {
+    /// Shows the template only, never resolved values
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({:?})", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn resolve_environment_variables() {
+        env::set_var("GRIFFIN_TEST_TOKEN", "s3cr3t");
+        let secret = Secret::new("Bearer ${GRIFFIN_TEST_TOKEN}");
+        assert_eq!(secret.resolve(), Ok("Bearer s3cr3t".to_owned()));
+        assert_eq!(Secret::new("plain").resolve(), Ok("plain".to_owned()));
+        assert_eq!(
+            Secret::new("${GRIFFIN_TEST_UNSET}").resolve(),
+            Err(SecretError {
+                var: "GRIFFIN_TEST_UNSET".to_owned()
+            })
+        );
+    }
+}
@@ -0,0 +1,852 @@
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+    time::Duration,
+};
+
+use super::{
+    Alert, CertExpiry, Config, DnsOptions, HealthCheck, HealthCheckMethod, HttpMethod, RecordType,
+    Scheme, Service, StatusPolicy, TlsOptions,
+};
+use crate::check::{decode_hex, dns, http::Url};
+
+/// Intervals shorter than this are allowed but likely a mistake
+pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Regex expression to match a single label of a hostname
+    static ref LABEL_RE: Regex = Regex::new(r"^[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?$").unwrap();
+
+    /// Regex expression to match a HTTP header name
+    static ref HEADER_NAME_RE: Regex = Regex::new(r"^[!#$%&'*+.^_`|~0-9A-Za-z-]+$").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How bad a config problem is
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single problem found while validating a config
+pub struct Problem {
+    pub severity: Severity,
+    /// Where the problem is, like `services[0].health[1].port`
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Collects problems found in a config
+#[derive(Default)]
+struct Validator {
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn error(&mut self, path: String, message: String) {
+        self.problems.push(Problem {
+            severity: Severity::Error,
+            path,
+            message,
+        });
+    }
+
+    fn warning(&mut self, path: String, message: String) {
+        self.problems.push(Problem {
+            severity: Severity::Warning,
+            path,
+            message,
+        });
+    }
+
+    fn service(&mut self, path: &str, service: &Service) {
+        if service.name.trim().is_empty() {
+            self.error(format!("{}.name", path), "must not be empty".to_owned());
+        }
+        if !is_valid_host(&service.host) {
+            self.error(
+                format!("{}.host", path),
+                format!("{:?} is not a valid hostname or IP address", service.host),
+            );
+        }
+        if service.health.is_empty() {
+            self.warning(
+                format!("{}.health", path),
+                "no health checks configured".to_owned(),
+            );
+        }
+        for (i, check) in service.health.iter().enumerate() {
+            self.check(&format!("{}.health[{}]", path, i), check);
+        }
+        match service.policy {
+            StatusPolicy::Quorum(n) if n == 0 || n as usize > service.health.len() => {
+                self.error(
+                    format!("{}.policy.quorum", path),
+                    format!(
+                        "must be between 1 and the {} checks of the service",
+                        service.health.len()
+                    ),
+                );
+            }
+            StatusPolicy::Weighted(fraction) if !(fraction > 0.0 && fraction <= 1.0) => {
+                self.error(
+                    format!("{}.policy.weighted", path),
+                    format!("{} is not a fraction between 0 and 1", fraction),
+                );
+            }
+            StatusPolicy::Weighted(_) => {
+                if service.health.iter().all(|c| c.weight == 0) {
+                    self.error(
+                        format!("{}.health", path),
+                        "checks of a weighted policy need some weight".to_owned(),
+                    );
+                }
+            }
+            _ => {
+                for (i, check) in service.health.iter().enumerate() {
+                    if check.weight != 1 {
+                        self.error(
+                            format!("{}.health[{}].weight", path, i),
+                            "only applies to services with a weighted policy".to_owned(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check(&mut self, path: &str, check: &HealthCheck) {
+        if let HealthCheckMethod::Http = check.method {
+            if check.endpoint.is_none() {
+                self.error(
+                    format!("{}.endpoint", path),
+                    "is required for http checks".to_owned(),
+                );
+            }
+        }
+        let needs_port = matches!(
+            check.method,
+            HealthCheckMethod::Tcp | HealthCheckMethod::Udp | HealthCheckMethod::Grpc
+        );
+        if needs_port && check.port.is_none() {
+            self.error(
+                format!("{}.port", path),
+                format!("is required for {} checks", check.method),
+            );
+        }
+        if matches!(
+            check.method,
+            HealthCheckMethod::Tcp | HealthCheckMethod::Udp | HealthCheckMethod::WebSocket
+        ) {
+            if let Some(hex) = &check.payload_hex {
+                if check.payload.is_some() {
+                    self.error(
+                        format!("{}.payload_hex", path),
+                        "can't be used together with payload".to_owned(),
+                    );
+                }
+                if let Err(e) = decode_hex(hex) {
+                    self.error(format!("{}.payload_hex", path), e);
+                }
+            }
+        } else {
+            let socket_only = [
+                ("payload", check.payload.is_some()),
+                ("payload_hex", check.payload_hex.is_some()),
+                ("banner", check.banner.is_some()),
+            ];
+            for (key, _) in socket_only.iter().filter(|(_, set)| *set) {
+                self.error(
+                    format!("{}.{}", path, key),
+                    "only applies to tcp, udp and websocket checks".to_owned(),
+                );
+            }
+        }
+        if check.scheme != Scheme::Http
+            && !matches!(
+                check.method,
+                HealthCheckMethod::Http | HealthCheckMethod::Grpc | HealthCheckMethod::WebSocket
+            )
+        {
+            self.error(
+                format!("{}.scheme", path),
+                "only applies to http, grpc and websocket checks".to_owned(),
+            );
+        }
+        if check.grpc_service.is_some() && !matches!(check.method, HealthCheckMethod::Grpc) {
+            self.error(
+                format!("{}.grpc_service", path),
+                "only applies to grpc checks".to_owned(),
+            );
+        }
+        if !matches!(check.method, HealthCheckMethod::Http) {
+            let http_only = [
+                ("expect", !check.expect.is_empty()),
+                ("http_method", check.http_method != HttpMethod::Get),
+                ("body", check.body.is_some()),
+            ];
+            for (key, _) in http_only.iter().filter(|(_, set)| *set) {
+                self.error(
+                    format!("{}.{}", path, key),
+                    "only applies to http checks".to_owned(),
+                );
+            }
+        }
+        if !matches!(
+            check.method,
+            HealthCheckMethod::Http | HealthCheckMethod::WebSocket
+        ) {
+            let request_only = [
+                ("headers", !check.headers.is_empty()),
+                ("basic_auth", check.basic_auth.is_some()),
+                ("bearer_token", check.bearer_token.is_some()),
+            ];
+            for (key, _) in request_only.iter().filter(|(_, set)| *set) {
+                self.error(
+                    format!("{}.{}", path, key),
+                    "only applies to http and websocket checks".to_owned(),
+                );
+            }
+        }
+        if let HealthCheckMethod::Exec = check.method {
+            match &check.command {
+                None => self.error(
+                    format!("{}.command", path),
+                    "is required for exec checks".to_owned(),
+                ),
+                Some(command) => {
+                    if command.contains('/') && !Path::new(command).is_file() {
+                        self.error(
+                            format!("{}.command", path),
+                            format!("{} does not exist", command),
+                        );
+                    }
+                }
+            }
+        } else {
+            let exec_only = [
+                ("command", check.command.is_some()),
+                ("args", !check.args.is_empty()),
+            ];
+            for (key, _) in exec_only.iter().filter(|(_, set)| *set) {
+                self.error(
+                    format!("{}.{}", path, key),
+                    "only applies to exec checks".to_owned(),
+                );
+            }
+        }
+        if let HealthCheckMethod::Dns = check.method {
+            self.dns(&format!("{}.dns", path), &check.dns);
+        } else if !check.dns.is_empty() {
+            self.error(
+                format!("{}.dns", path),
+                "only applies to dns checks".to_owned(),
+            );
+        }
+        match check.method {
+            HealthCheckMethod::Postgres | HealthCheckMethod::Mysql
+                if check.database.user.is_none() =>
+            {
+                self.error(
+                    format!("{}.database.user", path),
+                    format!("is required for {} checks", check.method),
+                );
+            }
+            HealthCheckMethod::Postgres | HealthCheckMethod::Mysql => {}
+            HealthCheckMethod::Redis => {
+                // redis only takes a user along with a password
+                if check.database.user.is_some() && check.database.password.is_none() {
+                    self.error(
+                        format!("{}.database.password", path),
+                        "is required for redis checks with a user".to_owned(),
+                    );
+                }
+                if let Some(name) = &check.database.name {
+                    if name.parse::<u32>().is_err() {
+                        self.error(
+                            format!("{}.database.name", path),
+                            format!("{:?} is not a redis database index", name),
+                        );
+                    }
+                }
+            }
+            _ if !check.database.is_empty() => self.error(
+                format!("{}.database", path),
+                "only applies to postgres, mysql and redis checks".to_owned(),
+            ),
+            _ => {}
+        }
+        if let HealthCheckMethod::TlsCert = check.method {
+            let ignored = [
+                ("ca_file", check.tls.ca_file.is_some()),
+                ("insecure_skip_verify", check.tls.insecure_skip_verify),
+            ];
+            for (key, _) in ignored.iter().filter(|(_, set)| *set) {
+                self.warning(
+                    format!("{}.tls.{}", path, key),
+                    "is ignored by tls_cert checks".to_owned(),
+                );
+            }
+            let expiry = &check.expiry;
+            if expiry.critical.as_duration() > expiry.warning.as_duration() {
+                self.error(
+                    format!("{}.expiry.critical", path),
+                    format!(
+                        "{} is longer than warning {}",
+                        expiry.critical, expiry.warning
+                    ),
+                );
+            }
+        } else {
+            if check.scheme == Scheme::Http && !check.tls.is_empty() {
+                self.error(
+                    format!("{}.tls", path),
+                    "only applies to https and tls_cert checks".to_owned(),
+                );
+            }
+            if check.tls.insecure_skip_verify {
+                self.warning(
+                    format!("{}.tls.insecure_skip_verify", path),
+                    "certificates are not verified".to_owned(),
+                );
+            }
+            if check.expiry != CertExpiry::default() {
+                self.error(
+                    format!("{}.expiry", path),
+                    "only applies to tls_cert checks".to_owned(),
+                );
+            }
+        }
+        self.tls(&format!("{}.tls", path), &check.tls);
+        if check.basic_auth.is_some() && check.bearer_token.is_some() {
+            self.error(
+                format!("{}.bearer_token", path),
+                "can't be used together with basic_auth".to_owned(),
+            );
+        }
+        for name in check.headers.keys() {
+            if !HEADER_NAME_RE.is_match(name) {
+                self.error(
+                    format!("{}.headers", path),
+                    format!("{:?} is not a valid header name", name),
+                );
+            } else if let HealthCheckMethod::WebSocket = check.method {
+                let lower = name.to_ascii_lowercase();
+                if lower == "upgrade"
+                    || lower == "connection"
+                    || lower.starts_with("sec-websocket-")
+                {
+                    self.error(
+                        format!("{}.headers", path),
+                        format!("{:?} is set by websocket checks", name),
+                    );
+                }
+            }
+        }
+        // the config may be checked where the secrets aren't available
+        for (key, secret) in check.secrets() {
+            match secret.resolve() {
+                Err(e) => self.warning(format!("{}.{}", path, key), e.to_string()),
+                // these end up in request headers
+                Ok(value)
+                    if (key.starts_with("headers.")
+                        || key.starts_with("basic_auth.")
+                        || key == "bearer_token")
+                        && value.contains(&['\r', '\n'][..]) =>
+                {
+                    self.error(
+                        format!("{}.{}", path, key),
+                        "can't hold line breaks".to_owned(),
+                    );
+                }
+                Ok(_) => {}
+            }
+        }
+        if let Some(endpoint) = &check.endpoint {
+            if !endpoint.starts_with('/') {
+                self.error(
+                    format!("{}.endpoint", path),
+                    format!("{:?} must start with /", endpoint),
+                );
+            }
+        }
+        if check.port == Some(0) {
+            self.error(
+                format!("{}.port", path),
+                "must be between 1 and 65535".to_owned(),
+            );
+        }
+        for (key, threshold) in &[
+            ("failure_threshold", check.failure_threshold),
+            ("success_threshold", check.success_threshold),
+        ] {
+            if *threshold == 0 {
+                self.error(format!("{}.{}", path, key), "must be at least 1".to_owned());
+            }
+        }
+        if let Some(timeout) = &check.timeout {
+            if timeout.as_duration() > check.interval.as_duration() {
+                self.warning(
+                    format!("{}.timeout", path),
+                    format!(
+                        "{} is longer than the interval {} of the check",
+                        timeout, check.interval
+                    ),
+                );
+            }
+        }
+        if check.interval.as_duration() < MIN_INTERVAL {
+            self.warning(
+                format!("{}.interval", path),
+                format!(
+                    "{} is shorter than {:?} and may flood the host",
+                    check.interval, MIN_INTERVAL
+                ),
+            );
+        }
+    }
+
+    fn tls(&mut self, path: &str, tls: &TlsOptions) {
+        if tls.client_cert.is_some() != tls.client_key.is_some() {
+            self.error(
+                path.to_owned(),
+                "client_cert and client_key must be set together".to_owned(),
+            );
+        }
+        let files = [
+            ("ca_file", &tls.ca_file),
+            ("client_cert", &tls.client_cert),
+            ("client_key", &tls.client_key),
+        ];
+        for (key, file) in files.iter() {
+            if let Some(file) = file {
+                if !file.is_file() {
+                    self.error(
+                        format!("{}.{}", path, key),
+                        format!("{} does not exist", file.display()),
+                    );
+                }
+            }
+        }
+    }
+
+    fn dns(&mut self, path: &str, dns: &DnsOptions) {
+        if let Some(resolver) = &dns.resolver {
+            if dns::resolver_addr(resolver).is_none() {
+                self.error(
+                    format!("{}.resolver", path),
+                    format!("{:?} is not an IP address with an optional port", resolver),
+                );
+            }
+        }
+        for value in &dns.expect {
+            let valid = match dns.record_type {
+                RecordType::A => value.parse::<Ipv4Addr>().is_ok(),
+                RecordType::Aaaa => value.parse::<Ipv6Addr>().is_ok(),
+                _ => true,
+            };
+            if !valid {
+                self.error(
+                    format!("{}.expect", path),
+                    format!("{:?} is not a valid {} record", value, dns.record_type),
+                );
+            }
+        }
+    }
+
+    fn alert(&mut self, path: &str, alert: &Alert) {
+        match alert {
+            Alert::Email { from, to, .. } => {
+                if !from.contains('@') {
+                    self.error(
+                        format!("{}.from", path),
+                        format!("{:?} is not an email address", from),
+                    );
+                }
+                for rcpt in to.split(',').map(str::trim) {
+                    if !rcpt.contains('@') {
+                        self.error(
+                            format!("{}.to", path),
+                            format!("{:?} is not an email address", rcpt),
+                        );
+                    }
+                }
+            }
+            Alert::Webhook { url } => {
+                if let Err(e) = url.parse::<Url>() {
+                    self.error(format!("{}.url", path), e.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Whether a string is an IP address or a valid hostname
+fn is_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty() && host.len() <= 253 && host.split('.').all(|l| LABEL_RE.is_match(l))
+}
+
+impl Config {
+    /// Checks the config for mistakes deserialization can't catch and returns
+    /// every problem found
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut v = Validator::default();
+        let mut names = HashSet::new();
+        for (i, service) in self.services.iter().enumerate() {
+            let path = format!("services[{}]", i);
+            if !names.insert(service.name.as_str()) {
+                v.error(
+                    format!("{}.name", path),
+                    format!("{:?} is used by more than one service", service.name),
+                );
+            }
+            v.service(&path, service);
+        }
+        for (i, alert) in self.alerts.iter().enumerate() {
+            v.alert(&format!("alerts[{}]", i), alert);
+        }
+        v.problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn valid_hosts() {
+        assert!(is_valid_host("foo.example.com"));
+        assert!(is_valid_host("localhost"));
+        assert!(is_valid_host("10.0.0.1"));
+        assert!(is_valid_host("::1"));
+        assert!(!is_valid_host("foo_bar.example.com"));
+        assert!(!is_valid_host("-foo.example.com"));
+        assert!(!is_valid_host("http://foo.example.com"));
+        assert!(!is_valid_host(""));
+    }
+
+    #[test]
+    fn validates_dns_options() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: dns
+                  dns:
+                    resolver: 10.0.0.2:5353
+                    expect: [10.0.0.1]
+                - method: dns
+                  dns:
+                    resolver: dns.example.com
+                    record_type: AAAA
+                    expect: [10.0.0.1]
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let paths: Vec<String> = config.validate().into_iter().map(|p| p.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "services[0].health[1].dns.resolver",
+                "services[0].health[1].dns.expect"
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_udp_payload() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: udp
+                  port: 123
+                  payload_hex: 1b00 0000
+                - method: udp
+                  payload: ping
+                  payload_hex: 1g
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].health[1].port: is required for udp checks",
+                "error: services[0].health[1].payload_hex: can't be used together with payload",
+                "error: services[0].health[1].payload_hex: \"1g\" is not valid hex",
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_grpc_checks() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: grpc
+                  port: 50051
+                  scheme: https
+                  grpc_service: orders
+                - method: grpc
+                - method: tcp
+                  port: 50051
+                  scheme: https
+                  grpc_service: orders
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].health[1].port: is required for grpc checks",
+                "error: services[0].health[2].scheme: only applies to http, grpc and websocket checks",
+                "error: services[0].health[2].grpc_service: only applies to grpc checks",
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_websocket_checks() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: websocket
+                  endpoint: /realtime
+                  scheme: https
+                  bearer_token: abc
+                  payload: ping
+                  banner: pong
+                  timeout: 2s
+                - method: tcp
+                  port: 80
+                  bearer_token: abc
+                - method: websocket
+                  headers:
+                    Host: api.example.com
+                    Sec-WebSocket-Protocol: chat
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].health[1].bearer_token: only applies to http and websocket checks",
+                "error: services[0].health[2].headers: \"Sec-WebSocket-Protocol\" is set by websocket checks",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_line_breaks_in_headers() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: http
+                  endpoint: /status
+                  headers:
+                    X-Token: "abc\r\nX-Admin: 1"
+                  bearer_token: "abc\n"
+                  body: "{\n}"
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].health[0].headers.X-Token: can't hold line breaks",
+                "error: services[0].health[0].bearer_token: can't hold line breaks",
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_timeouts_and_thresholds() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: ping
+                  interval: 10s
+                  timeout: 2s
+                  retries: 2
+                  failure_threshold: 3
+                  success_threshold: 2
+                - method: ping
+                  interval: 10s
+                  timeout: 1min
+                  failure_threshold: 0
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].health[1].failure_threshold: must be at least 1",
+                "warning: services[0].health[1].timeout: 1min is longer than the interval 10s of the check",
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_status_policies() {
+        let config = r###"
+            services:
+            - name: Quorum
+              host: foo.example.com
+              policy: {quorum: 3}
+              health:
+                - method: ping
+                - method: ping
+            - name: Weighted
+              host: foo.example.com
+              policy: {weighted: 1.5}
+              health:
+                - method: ping
+                  weight: 2
+            - name: All
+              host: foo.example.com
+              health:
+                - method: ping
+                  weight: 2
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].policy.quorum: must be between 1 and the 2 checks of the service",
+                "error: services[1].policy.weighted: 1.5 is not a fraction between 0 and 1",
+                "error: services[2].health[0].weight: only applies to services with a weighted policy",
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_database_options() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: postgres
+                  database: {user: griffin, name: orders}
+                - method: mysql
+                - method: redis
+                  database: {password: s3cr3t, name: cache}
+                - method: redis
+                  database: {user: griffin, name: "2"}
+                - method: tcp
+                  port: 5432
+                  database: {user: griffin}
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems: Vec<String> = config.validate().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "error: services[0].health[1].database.user: is required for mysql checks",
+                "error: services[0].health[2].database.name: \"cache\" is not a redis database index",
+                "error: services[0].health[3].database.password: is required for redis checks with a user",
+                "error: services[0].health[4].database: only applies to postgres, mysql and redis checks",
+            ]
+        );
+
+        // there is no query to set for any database
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: redis
+                  database: {query: INFO}
+        "###;
+        assert!(serde_yaml::from_str::<Config>(config).is_err());
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let config = r###"
+            services:
+            - name: Foo
+              host: foo.example.com
+              health:
+                - method: http
+                  port: 0
+                - method: ping
+                  interval: 30ms
+                  payload: hello
+                  headers:
+                    X-Token: ${GRIFFIN_TEST_UNSET_TOKEN}
+            - name: Foo
+              host: foo..example.com
+              health:
+                - method: ping
+                  dns:
+                    name: example.com
+                - method: exec
+                  command: /nonexistent/check_foo
+                - method: tls_cert
+                  expiry:
+                    warning: 3d
+                  tls:
+                    insecure_skip_verify: true
+            alerts:
+              - type: Webhook
+                url: mywebhook.com
+        "###;
+        let config: Config = serde_yaml::from_str(config).unwrap();
+        let problems = config.validate();
+        let problems: Vec<(Severity, &str)> = problems
+            .iter()
+            .map(|p| (p.severity, p.path.as_str()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (Severity::Error, "services[0].health[0].endpoint"),
+                (Severity::Error, "services[0].health[0].port"),
+                (Severity::Error, "services[0].health[1].payload"),
+                (Severity::Error, "services[0].health[1].headers"),
+                (Severity::Warning, "services[0].health[1].headers.X-Token"),
+                (Severity::Warning, "services[0].health[1].interval"),
+                (Severity::Error, "services[1].name"),
+                (Severity::Error, "services[1].host"),
+                (Severity::Error, "services[1].health[0].dns"),
+                (Severity::Error, "services[1].health[1].command"),
+                (
+                    Severity::Warning,
+                    "services[1].health[2].tls.insecure_skip_verify"
+                ),
+                (Severity::Error, "services[1].health[2].expiry.critical"),
+                (Severity::Error, "alerts[0].url"),
+            ]
+        );
+    }
+}
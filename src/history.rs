@@ -0,0 +1,320 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    check::{CheckResult, Failure, Status},
+    config::{Config, HistoryOptions},
+    scheduler::Report,
+};
+
+/// How often results older than the retention are dropped from the file
+const COMPACT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A check result as it is stored, one JSON object per line
+pub struct Record {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub service: String,
+    /// Index of the check in the service
+    pub check: usize,
+    pub method: String,
+    pub status: Status,
+    pub latency_ms: f64,
+    pub error: Option<String>,
+}
+
+impl Record {
+    /// Record of a report of a check of a config
+    pub fn new(config: &Config, report: &Report) -> Self {
+        let service = &config.services[report.service];
+        Self {
+            timestamp: report
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            service: service.name.clone(),
+            check: report.check,
+            method: service.health[report.check].method.to_string(),
+            status: report.result.status,
+            latency_ms: report.result.latency.as_secs_f64() * 1000.0,
+            error: report.result.failure.as_ref().map(|f| f.to_string()),
+        }
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp)
+    }
+
+    /// Report to replay the record with. None if the config no longer has
+    /// its service or check.
+    ///
+    /// Only the status, latency and error text of the result are restored.
+    pub fn report(&self, config: &Config) -> Option<Report> {
+        let service = config
+            .services
+            .iter()
+            .position(|s| s.name == self.service)?;
+        let check = config.services[service].health.get(self.check)?;
+        if check.method.to_string() != self.method {
+            return None;
+        }
+        let mut result =
+            CheckResult::success(Duration::from_secs_f64(self.latency_ms.max(0.0) / 1000.0));
+        result.status = self.status;
+        result.failure = self.error.clone().map(Failure::Recorded);
+        Some(Report {
+            service,
+            check: self.check,
+            time: self.time(),
+            result,
+        })
+    }
+}
+
+/// Append-only file of check results that drops results older than its
+/// retention from time to time
+pub struct Store {
+    path: PathBuf,
+    retention: Duration,
+    file: File,
+    /// When results were last dropped
+    compacted: SystemTime,
+}
+
+impl Store {
+    /// Opens the file of a history, creating it and its directory if needed,
+    /// and drops results older than the retention
+    pub fn open(options: &HistoryOptions, now: SystemTime) -> io::Result<Self> {
+        if let Some(dir) = options.path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let mut store = Self {
+            path: options.path.clone(),
+            retention: options.retention.as_duration(),
+            file: Self::open_file(&options.path)?,
+            compacted: now,
+        };
+        store.compact(now)?;
+        Ok(store)
+    }
+
+    /// Opens a file for appending. A line cut short by a crash gets ended
+    /// so it doesn't swallow the next record.
+    fn open_file(path: &Path) -> io::Result<File> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        if file.metadata()?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(file)
+    }
+
+    /// Reads every stored record, oldest first. Lines that can't be parsed,
+    /// like one cut short by a crash, are skipped.
+    pub fn load(&self) -> io::Result<Vec<Record>> {
+        let mut records = Vec::new();
+        let mut skipped = 0;
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(record) => records.push(record),
+                Err(_) => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            warn!(
+                "skipped {} unreadable lines of {}",
+                skipped,
+                self.path.display()
+            );
+        }
+        Ok(records)
+    }
+
+    /// Appends a record, dropping old ones first when they are due
+    pub fn append(&mut self, record: &Record) -> io::Result<()> {
+        let now = record.time();
+        if now
+            .duration_since(self.compacted)
+            .is_ok_and(|d| d >= COMPACT_INTERVAL)
+        {
+            self.compact(now)?;
+        }
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+
+    /// Rewrites the file without the records older than the retention
+    fn compact(&mut self, now: SystemTime) -> io::Result<()> {
+        let cutoff = now.checked_sub(self.retention).unwrap_or(UNIX_EPOCH);
+        let records = self.load()?;
+        let kept: Vec<&Record> = records.iter().filter(|r| r.time() >= cutoff).collect();
+        self.compacted = now;
+        if kept.len() == records.len() {
+            return Ok(());
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        for record in kept {
+            serde_json::to_writer(&mut w, record)?;
+            w.write_all(b"\n")?;
+        }
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        self.file = Self::open_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn config() -> Config {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: http
+                  endpoint: /status
+                - method: ping
+        "###;
+        Config::new(config.as_bytes()).unwrap()
+    }
+
+    fn report(check: usize, secs: u64, failure: Option<Failure>) -> Report {
+        Report {
+            service: 0,
+            check,
+            time: UNIX_EPOCH + Duration::from_secs(secs),
+            result: match failure {
+                Some(failure) => CheckResult::failure(Duration::from_millis(12), failure),
+                None => CheckResult::success(Duration::from_millis(12)),
+            },
+        }
+    }
+
+    /// Options for a fresh file under the temp dir
+    fn options(name: &str, retention: &str) -> HistoryOptions {
+        let path = std::env::temp_dir()
+            .join(format!("griffin-history-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_file(&path);
+        let options = format!("{{path: {}, retention: {}}}", path.display(), retention);
+        serde_yaml::from_str(&options).unwrap()
+    }
+
+    #[test]
+    fn converts_reports() {
+        let config = config();
+        let record = Record::new(&config, &report(1, 1_600_000_000, Some(Failure::Timeout)));
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"timestamp":1600000000000,"service":"Foo Web Service","check":1,"method":"ping","status":"critical","latency_ms":12.0,"error":"timed out"}"#
+        );
+
+        let replayed = record.report(&config).unwrap();
+        assert_eq!((replayed.service, replayed.check), (0, 1));
+        assert_eq!(replayed.time, record.time());
+        assert_eq!(replayed.result.status, Status::Critical);
+        assert_eq!(replayed.result.latency, Duration::from_millis(12));
+        assert_eq!(
+            replayed.result.failure,
+            Some(Failure::Recorded("timed out".to_owned()))
+        );
+
+        // the check is gone or changed
+        let other = Record {
+            method: "tcp".to_owned(),
+            ..record.clone()
+        };
+        assert_eq!(other.report(&config).map(|r| r.check), None);
+        let other = Record {
+            service: "Bar".to_owned(),
+            ..record
+        };
+        assert_eq!(other.report(&config).map(|r| r.check), None);
+    }
+
+    #[test]
+    fn appends_and_loads_records() {
+        let config = config();
+        let options = options("append.jsonl", "1h");
+        let mut store = Store::open(&options, UNIX_EPOCH).unwrap();
+        store
+            .append(&Record::new(&config, &report(0, 10, None)))
+            .unwrap();
+        store
+            .append(&Record::new(
+                &config,
+                &report(1, 20, Some(Failure::Timeout)),
+            ))
+            .unwrap();
+        drop(store);
+
+        // a line cut short by a crash
+        let mut file = OpenOptions::new().append(true).open(&options.path).unwrap();
+        file.write_all(b"{\"timestamp\":30").unwrap();
+
+        let mut store = Store::open(&options, UNIX_EPOCH + Duration::from_secs(40)).unwrap();
+        store
+            .append(&Record::new(&config, &report(0, 50, None)))
+            .unwrap();
+        let records = store.load().unwrap();
+        let times: Vec<u64> = records.iter().map(|r| r.timestamp / 1000).collect();
+        assert_eq!(times, vec![10, 20, 50]);
+        assert_eq!(records[1].error.as_deref(), Some("timed out"));
+    }
+
+    #[test]
+    fn drops_records_past_retention() {
+        let config = config();
+        let options = options("retention.jsonl", "1h");
+        let mut store = Store::open(&options, UNIX_EPOCH).unwrap();
+        store
+            .append(&Record::new(&config, &report(0, 0, None)))
+            .unwrap();
+        store
+            .append(&Record::new(&config, &report(0, 1800, None)))
+            .unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+
+        // compacts once an hour passed since the last time
+        store
+            .append(&Record::new(&config, &report(1, 3700, None)))
+            .unwrap();
+        let times: Vec<u64> = store
+            .load()
+            .unwrap()
+            .iter()
+            .map(|r| r.timestamp / 1000)
+            .collect();
+        assert_eq!(times, vec![1800, 3700]);
+
+        let store = Store::open(&options, UNIX_EPOCH + Duration::from_secs(5500)).unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+    }
+}
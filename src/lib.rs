@@ -1,5 +1,11 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod alert;
 pub mod app;
+pub mod check;
 pub mod config;
+pub mod history;
+pub mod scheduler;
+pub mod server;
+pub mod state;
@@ -0,0 +1,206 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    check::{self, CheckResult},
+    config::Config,
+};
+
+/// Longest delay before the first run of a check
+const MAX_SPLAY: Duration = Duration::from_secs(30);
+
+/// Fraction of the interval by which each run may move earlier or later
+const JITTER_RATIO: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+/// Result of a scheduled health check run
+pub struct Report {
+    /// Index of the service in the config
+    pub service: usize,
+    /// Index of the health check in the service
+    pub check: usize,
+    pub time: SystemTime,
+    pub result: CheckResult,
+}
+
+/// Runs every health check of a config repeatedly on its own interval
+pub struct Scheduler {
+    config: Arc<Config>,
+}
+
+impl Scheduler {
+    /// Creates a new scheduler for a config
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+
+    /// Starts a thread for every health check and returns the receiving end of
+    /// their reports. Checks keep running until the receiver is dropped.
+    pub fn start(&self) -> Receiver<Report> {
+        let (tx, rx) = mpsc::channel();
+        for (s, service) in self.config.services.iter().enumerate() {
+            for c in 0..service.health.len() {
+                let config = Arc::clone(&self.config);
+                let tx = tx.clone();
+                thread::Builder::new()
+                    .name(format!("check-{}-{}", s, c))
+                    .spawn(move || run_check(&config, s, c, &tx))
+                    .expect("failed to spawn health check thread");
+            }
+        }
+        rx
+    }
+}
+
+/// Runs every health check of a config exactly once, all at the same time,
+/// and returns their reports in config order
+pub fn run_once(config: &Config) -> Vec<Report> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = config
+            .services
+            .iter()
+            .enumerate()
+            .flat_map(|(s, service)| {
+                service.health.iter().enumerate().map(move |(c, health)| {
+                    scope.spawn(move || Report {
+                        service: s,
+                        check: c,
+                        time: SystemTime::now(),
+                        result: check::run(service, health),
+                    })
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("health check thread panicked"))
+            .collect()
+    })
+}
+
+/// Runs a single health check forever
+fn run_check(config: &Config, s: usize, c: usize, tx: &Sender<Report>) {
+    let service = &config.services[s];
+    let health = &service.health[c];
+    let interval = health.interval.as_duration();
+
+    thread::sleep(splay(interval));
+    loop {
+        let start = Instant::now();
+        let report = Report {
+            service: s,
+            check: c,
+            time: SystemTime::now(),
+            result: check::run(service, health),
+        };
+        if tx.send(report).is_err() {
+            return;
+        }
+        thread::sleep(jitter(interval).saturating_sub(start.elapsed()));
+    }
+}
+
+/// Random delay before the first run so checks don't all fire at once
+fn splay(interval: Duration) -> Duration {
+    interval.min(MAX_SPLAY).mul_f64(random())
+}
+
+/// Interval moved randomly by up to `JITTER_RATIO` in either direction
+fn jitter(interval: Duration) -> Duration {
+    interval.mul_f64(1.0 + JITTER_RATIO * (2.0 * random() - 1.0))
+}
+
+/// Random number in `[0, 1)`
+fn random() -> f64 {
+    // every RandomState is seeded differently, which is random enough for jitter
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let interval = Duration::from_secs(10);
+        for _ in 0..100 {
+            let d = jitter(interval);
+            assert!(d >= Duration::from_secs(9) && d <= Duration::from_secs(11));
+            assert!(splay(interval) < interval);
+        }
+        assert!(splay(Duration::from_secs(3600)) < MAX_SPLAY);
+    }
+
+    #[test]
+    fn runs_every_check_once() {
+        let config = r###"
+            services:
+            - name: First
+              host: 127.0.0.1
+              health:
+                - method: ping
+            - name: Second
+              host: 127.0.0.1
+              health:
+                - method: ping
+                - method: ping
+            "###;
+        let config = Config::new(config.as_bytes()).unwrap();
+        let reports = run_once(&config);
+        let indexes: Vec<_> = reports.iter().map(|r| (r.service, r.check)).collect();
+        assert_eq!(indexes, vec![(0, 0), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn runs_checks_repeatedly() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut rdr = BufReader::new(stream.unwrap());
+                let mut line = String::new();
+                while rdr.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let _ = rdr
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            }
+        });
+
+        let config = format!(
+            r###"
+            services:
+            - name: Local
+              host: 127.0.0.1
+              health:
+                - method: http
+                  endpoint: /status
+                  port: {}
+                  interval: 20ms
+            "###,
+            port
+        );
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let rx = Scheduler::new(config).start();
+        for _ in 0..3 {
+            let report = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!((report.service, report.check), (0, 0));
+            assert!(report.result.is_success());
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use super::{unix_time, Response};
+use crate::{
+    check::CheckResult,
+    state::{ServiceStatus, StatusBoard},
+};
+
+#[derive(Debug, Serialize)]
+/// A service with its status and checks
+struct ServiceView<'a> {
+    name: &'a str,
+    host: &'a str,
+    status: ServiceStatus,
+    /// When the status last changed, in seconds since the unix epoch
+    since: u64,
+    reason: Option<&'a str>,
+    checks: Vec<CheckView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+/// A check with its state and latest result
+struct CheckView<'a> {
+    service: &'a str,
+    method: String,
+    port: Option<u16>,
+    endpoint: Option<&'a str>,
+    /// passing, failing or unknown once thresholds are applied
+    state: &'static str,
+    result: Option<ResultView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+/// Latest result of a check
+struct ResultView<'a> {
+    status: String,
+    /// When the check ran, in seconds since the unix epoch
+    time: u64,
+    latency_ms: f64,
+    attempts: u32,
+    failure: Option<String>,
+    status_code: Option<u16>,
+    packet_loss: Option<f32>,
+    cert_expiry: Option<u64>,
+    output: Option<&'a str>,
+}
+
+impl<'a> ResultView<'a> {
+    fn new(time: SystemTime, result: &'a CheckResult) -> Self {
+        Self {
+            status: result.status.to_string().to_lowercase(),
+            time: unix_time(time),
+            latency_ms: result.latency.as_secs_f64() * 1000.0,
+            attempts: result.attempts,
+            failure: result.failure.as_ref().map(|f| f.to_string()),
+            status_code: result.status_code,
+            packet_loss: result.packet_loss,
+            cert_expiry: result.cert_expiry.map(unix_time),
+            output: result.output.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorView {
+    error: String,
+}
+
+fn checks(board: &StatusBoard, service: usize) -> Vec<CheckView<'_>> {
+    let config = &board.config().services[service];
+    config
+        .health
+        .iter()
+        .enumerate()
+        .map(|(i, check)| CheckView {
+            service: &config.name,
+            method: check.method.to_string(),
+            port: check.port,
+            endpoint: check.endpoint.as_deref(),
+            state: match board.check(service, i).passing() {
+                Some(true) => "passing",
+                Some(false) => "failing",
+                None => "unknown",
+            },
+            result: board
+                .report(service, i)
+                .map(|r| ResultView::new(r.time, &r.result)),
+        })
+        .collect()
+}
+
+fn service(board: &StatusBoard, service: usize) -> ServiceView<'_> {
+    let config = &board.config().services[service];
+    let state = board.service(service);
+    ServiceView {
+        name: &config.name,
+        host: &config.host,
+        status: state.status,
+        since: unix_time(state.since),
+        reason: state.reason.as_deref(),
+        checks: checks(board, service),
+    }
+}
+
+fn not_found(message: String) -> Response {
+    Response {
+        status: 404,
+        ..Response::json(&ErrorView { error: message })
+    }
+}
+
+/// Answers a request for a path under /api
+pub(super) fn route(board: &StatusBoard, path: &str) -> Response {
+    let services = 0..board.config().services.len();
+    match path.trim_end_matches('/') {
+        "/api/services" => Response::json(&services.map(|i| service(board, i)).collect::<Vec<_>>()),
+        "/api/checks" => {
+            Response::json(&services.flat_map(|i| checks(board, i)).collect::<Vec<_>>())
+        }
+        path => match path.strip_prefix("/api/services/") {
+            Some(name) => match board.config().services.iter().position(|s| s.name == name) {
+                Some(i) => Response::json(&service(board, i)),
+                None => not_found(format!("no service named {:?}", name)),
+            },
+            None => not_found(format!("no such endpoint {}", path)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{check::Failure, config::Config, scheduler::Report};
+    use std::{
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    fn board() -> StatusBoard {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: http
+                  endpoint: /status
+                - method: ping
+            - name: Bar
+              host: bar.example.com
+              health:
+                - method: tcp
+                  port: 22
+        "###;
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let mut board = StatusBoard::new(config, UNIX_EPOCH);
+        board.update(&Report {
+            service: 0,
+            check: 1,
+            time: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            result: CheckResult::failure(Duration::from_millis(1500), Failure::Timeout),
+        });
+        board
+    }
+
+    fn json(response: Response) -> serde_json::Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[test]
+    fn lists_services() {
+        let response = route(&board(), "/api/services");
+        assert_eq!(response.status, 200);
+        let services = json(response);
+        assert_eq!(services.as_array().unwrap().len(), 2);
+
+        let foo = &services[0];
+        assert_eq!(foo["name"], "Foo Web Service");
+        assert_eq!(foo["status"], "down");
+        assert_eq!(foo["since"], 1_600_000_000);
+        assert_eq!(foo["reason"], "ping check failed: timed out");
+        assert_eq!(foo["checks"][0]["state"], "unknown");
+        assert_eq!(foo["checks"][0]["result"], serde_json::Value::Null);
+
+        let ping = &foo["checks"][1];
+        assert_eq!(ping["method"], "ping");
+        assert_eq!(ping["state"], "failing");
+        assert_eq!(ping["result"]["status"], "critical");
+        assert_eq!(ping["result"]["latency_ms"], 1500.0);
+        assert_eq!(ping["result"]["failure"], "timed out");
+
+        assert_eq!(services[1]["status"], "unknown");
+        assert_eq!(services[1]["since"], 0);
+    }
+
+    #[test]
+    fn finds_services_by_name() {
+        let board = board();
+        let bar = json(route(&board, "/api/services/Bar/"));
+        assert_eq!(bar["host"], "bar.example.com");
+        assert_eq!(bar["checks"][0]["port"], 22);
+
+        let response = route(&board, "/api/services/Baz");
+        assert_eq!(response.status, 404);
+        assert_eq!(json(response)["error"], "no service named \"Baz\"");
+        assert_eq!(route(&board, "/api/status").status, 404);
+    }
+
+    #[test]
+    fn lists_checks() {
+        let checks = json(route(&board(), "/api/checks"));
+        let services: Vec<&str> = checks
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["service"].as_str().unwrap())
+            .collect();
+        assert_eq!(services, vec!["Foo Web Service", "Foo Web Service", "Bar"]);
+        assert_eq!(checks[0]["endpoint"], "/status");
+    }
+}
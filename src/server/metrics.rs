@@ -0,0 +1,269 @@
+use std::fmt::Write;
+
+use super::{unix_time, Response};
+use crate::{
+    check::Status,
+    state::{ServiceStatus, StatusBoard},
+};
+
+/// Content type of the Prometheus text format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Escapes a label value of the Prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Writes the HELP and TYPE lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Labels of every check, by service and check index
+fn check_labels(board: &StatusBoard) -> Vec<Vec<String>> {
+    board
+        .config()
+        .services
+        .iter()
+        .map(|service| {
+            service
+                .health
+                .iter()
+                .enumerate()
+                .map(|(i, check)| {
+                    format!(
+                        "service=\"{}\",host=\"{}\",method=\"{}\",check=\"{}\"",
+                        escape(&service.name),
+                        escape(&service.host),
+                        check.method,
+                        i
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Renders the state of every service and check in the Prometheus text
+/// format. Checks are labelled by service, host, method and their index in
+/// the service since a service may have several checks of one method.
+pub(super) fn render(board: &StatusBoard) -> Response {
+    let services = &board.config().services;
+    let labels = check_labels(board);
+    let checks = || {
+        labels
+            .iter()
+            .enumerate()
+            .flat_map(|(s, checks)| checks.iter().enumerate().map(move |(c, l)| (s, c, l)))
+    };
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "griffin_service_status",
+        "gauge",
+        "Whether a service has a status, 1 for its current one",
+    );
+    for (s, service) in services.iter().enumerate() {
+        let current = board.service(s).status;
+        for status in &[
+            ServiceStatus::Up,
+            ServiceStatus::Degraded,
+            ServiceStatus::Down,
+            ServiceStatus::Unknown,
+        ] {
+            writeln!(
+                out,
+                "griffin_service_status{{service=\"{}\",host=\"{}\",status=\"{}\"}} {}",
+                escape(&service.name),
+                escape(&service.host),
+                status.to_string().to_lowercase(),
+                u8::from(current == *status)
+            )
+            .unwrap();
+        }
+    }
+
+    header(
+        &mut out,
+        "griffin_check_up",
+        "gauge",
+        "Whether a check is passing once thresholds are applied, missing until known",
+    );
+    for (s, c, labels) in checks() {
+        if let Some(passing) = board.check(s, c).passing() {
+            writeln!(out, "griffin_check_up{{{}}} {}", labels, u8::from(passing)).unwrap();
+        }
+    }
+
+    header(
+        &mut out,
+        "griffin_check_runs_total",
+        "counter",
+        "Runs of a check by result since the process started",
+    );
+    for (s, c, labels) in checks() {
+        let stats = board.stats(s, c);
+        for status in &[
+            Status::Ok,
+            Status::Warning,
+            Status::Critical,
+            Status::Unknown,
+        ] {
+            writeln!(
+                out,
+                "griffin_check_runs_total{{{},result=\"{}\"}} {}",
+                labels,
+                status.to_string().to_lowercase(),
+                stats.runs(*status)
+            )
+            .unwrap();
+        }
+    }
+
+    header(
+        &mut out,
+        "griffin_check_latency_seconds",
+        "histogram",
+        "Time runs of a check took",
+    );
+    for (s, c, labels) in checks() {
+        let stats = board.stats(s, c);
+        for (bound, count) in stats.latency_buckets() {
+            writeln!(
+                out,
+                "griffin_check_latency_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels,
+                bound.as_secs_f64(),
+                count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "griffin_check_latency_seconds_bucket{{{},le=\"+Inf\"}} {}",
+            labels,
+            stats.count()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "griffin_check_latency_seconds_sum{{{}}} {}",
+            labels,
+            stats.total_latency.as_secs_f64()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "griffin_check_latency_seconds_count{{{}}} {}",
+            labels,
+            stats.count()
+        )
+        .unwrap();
+    }
+
+    header(
+        &mut out,
+        "griffin_tls_cert_expiry_timestamp_seconds",
+        "gauge",
+        "Unix time the first certificate of the chain of a host expires",
+    );
+    for (s, c, labels) in checks() {
+        let expiry = board.report(s, c).and_then(|r| r.result.cert_expiry);
+        if let Some(expiry) = expiry {
+            writeln!(
+                out,
+                "griffin_tls_cert_expiry_timestamp_seconds{{{}}} {}",
+                labels,
+                unix_time(expiry)
+            )
+            .unwrap();
+        }
+    }
+
+    Response {
+        status: 200,
+        content_type: CONTENT_TYPE,
+        body: out.into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        check::{CheckResult, Failure},
+        config::Config,
+        scheduler::Report,
+    };
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    fn render_text(board: &StatusBoard) -> String {
+        let response = render(board);
+        assert_eq!(response.content_type, CONTENT_TYPE);
+        String::from_utf8(response.body).unwrap()
+    }
+
+    #[test]
+    fn renders_check_metrics() {
+        let config = r###"
+            services:
+            - name: Foo "Web" Service
+              host: foo.example.com
+              health:
+                - method: tls_cert
+                - method: ping
+        "###;
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let mut board = StatusBoard::new(config, SystemTime::now());
+        let mut result = CheckResult::success(Duration::from_millis(40));
+        result.cert_expiry = Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        for result in [
+            CheckResult::failure(Duration::from_secs(3), Failure::Timeout),
+            result,
+        ] {
+            board.update(&Report {
+                service: 0,
+                check: 0,
+                time: SystemTime::now(),
+                result,
+            });
+        }
+        let text = render_text(&board);
+
+        let labels =
+            r#"service="Foo \"Web\" Service",host="foo.example.com",method="tls_cert",check="0""#;
+        for line in &[
+            format!("griffin_check_up{{{}}} 1", labels),
+            format!("griffin_check_runs_total{{{},result=\"ok\"}} 1", labels),
+            format!("griffin_check_runs_total{{{},result=\"critical\"}} 1", labels),
+            format!("griffin_check_latency_seconds_bucket{{{},le=\"0.025\"}} 0", labels),
+            format!("griffin_check_latency_seconds_bucket{{{},le=\"0.05\"}} 1", labels),
+            format!("griffin_check_latency_seconds_bucket{{{},le=\"5\"}} 2", labels),
+            format!("griffin_check_latency_seconds_bucket{{{},le=\"+Inf\"}} 2", labels),
+            format!("griffin_check_latency_seconds_sum{{{}}} 3.04", labels),
+            format!("griffin_check_latency_seconds_count{{{}}} 2", labels),
+            format!(
+                "griffin_tls_cert_expiry_timestamp_seconds{{{}}} 1700000000",
+                labels
+            ),
+            r#"griffin_service_status{service="Foo \"Web\" Service",host="foo.example.com",status="unknown"} 1"#.to_owned(),
+            r#"griffin_service_status{service="Foo \"Web\" Service",host="foo.example.com",status="up"} 0"#.to_owned(),
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {} in\n{}", line, text);
+        }
+        // the ping check has not run yet
+        assert!(!text
+            .lines()
+            .any(|l| l.starts_with("griffin_check_up{") && l.contains("method=\"ping\"")));
+        assert!(text.contains("# TYPE griffin_check_latency_seconds histogram\n"));
+    }
+}
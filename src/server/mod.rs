@@ -0,0 +1,253 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::{check::percent_decode, state::StatusBoard};
+
+mod api;
+mod metrics;
+mod page;
+
+/// Longest request line and headers accepted from a client
+const MAX_HEAD_SIZE: u64 = 16 * 1024;
+
+/// Time a client gets to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+/// Method and path of a HTTP request
+pub struct Request {
+    pub method: String,
+    /// Decoded path without the query string
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A HTTP response to send to a client
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Response with a plain text body
+    pub fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    /// 200 response with a value serialized as JSON
+    pub fn json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_vec_pretty(value) {
+            Ok(body) => Self {
+                status: 200,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Self::text(500, &e.to_string()),
+        }
+    }
+
+    /// Writes the response, leaving out the body for HEAD requests
+    fn write_to<W: Write>(&self, mut w: W, with_body: bool) -> io::Result<()> {
+        write!(
+            w,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        if self.status == 405 {
+            write!(w, "Allow: GET, HEAD\r\n")?;
+        }
+        write!(w, "\r\n")?;
+        if with_body {
+            w.write_all(&self.body)?;
+        }
+        w.flush()
+    }
+}
+
+/// Reason phrase of the status codes the server sends
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Seconds since the unix epoch
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Listens on an address and answers requests about the services of a board
+/// in the background. Returns the address it listens on.
+pub fn start(listen: SocketAddr, board: Arc<Mutex<StatusBoard>>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(listen)?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let board = Arc::clone(&board);
+                    thread::spawn(move || handle(stream, &board));
+                }
+                Err(e) => warn!("could not accept connection: {}", e),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+/// Answers a single request on a connection
+fn handle(stream: TcpStream, board: &Mutex<StatusBoard>) {
+    let peer = stream
+        .peer_addr()
+        .map_or_else(|_| "client".to_owned(), |a| a.to_string());
+    let result = stream.set_read_timeout(Some(READ_TIMEOUT)).and_then(|_| {
+        let (response, with_body) = match read_request(&stream) {
+            Ok(request) => {
+                let board = board.lock().unwrap_or_else(|e| e.into_inner());
+                (route(&board, &request), request.method != "HEAD")
+            }
+            Err(e) => (Response::text(400, &e), true),
+        };
+        response.write_to(&stream, with_body)
+    });
+    if let Err(e) = result {
+        warn!("could not answer {}: {}", peer, e);
+    }
+}
+
+/// Picks the response to a request
+fn route(board: &StatusBoard, request: &Request) -> Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::text(405, "method not allowed\n");
+    }
+    if request.path == "/api" || request.path.starts_with("/api/") {
+        return api::route(board, &request.path);
+    }
+    if request.path == "/metrics" {
+        return metrics::render(board);
+    }
+    if request.path == "/" {
+        return page::render(board, SystemTime::now());
+    }
+    Response::text(404, "not found\n")
+}
+
+/// Reads the request line and headers of a request. Bodies are ignored
+/// since only GET and HEAD are served.
+fn read_request<R: Read>(rdr: R) -> Result<Request, String> {
+    let mut rdr = BufReader::new(rdr.take(MAX_HEAD_SIZE));
+    let mut line = String::new();
+    rdr.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut parts = line.trim_end().split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version))
+            if !method.is_empty() && target.starts_with('/') && version.starts_with("HTTP/1.") =>
+        {
+            (method.to_owned(), target)
+        }
+        _ => return Err(format!("bad request line {:?}\n", line.trim_end())),
+    };
+    let path = percent_decode(target.split('?').next().unwrap_or_default());
+
+    loop {
+        let mut header = String::new();
+        if rdr.read_line(&mut header).map_err(|e| e.to_string())? == 0 {
+            return Err("request headers are incomplete or too long\n".to_owned());
+        }
+        if header.trim_end().is_empty() {
+            break;
+        }
+    }
+    Ok(Request { method, path })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{check::http, config::Config};
+    use std::time::SystemTime;
+
+    #[test]
+    fn reads_requests() {
+        let request = "GET /api/services/Foo%20Web%20Service?pretty HTTP/1.1\r\n\
+                       Host: localhost\r\n\r\n";
+        assert_eq!(
+            read_request(request.as_bytes()),
+            Ok(Request {
+                method: "GET".to_owned(),
+                path: "/api/services/Foo Web Service".to_owned(),
+            })
+        );
+
+        assert!(read_request("GET /api/checks\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request("GET /api/checks HTTP/1.1\r\nHost: x\r\n".as_bytes()).is_err());
+        let long = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(20_000));
+        assert!(read_request(long.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn serves_requests() {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: ping
+        "###;
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let board = Arc::new(Mutex::new(StatusBoard::new(config, SystemTime::now())));
+        let addr = start("127.0.0.1:0".parse().unwrap(), board).unwrap();
+
+        let request = |req: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(req.as_bytes()).unwrap();
+            http::read_response(BufReader::new(stream), !req.starts_with("HEAD")).unwrap()
+        };
+        let response = request("GET /api/services HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        let json: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(json[0]["name"], "Foo Web Service");
+
+        let response = request("HEAD /api/checks HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+
+        let response = request("POST /api/services HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            (response.status, response.header("allow")),
+            (405, Some("GET, HEAD"))
+        );
+        let response = request("GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.header("content-type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(request("GET /favicon.ico HTTP/1.1\r\n\r\n").status, 404);
+        assert_eq!(request("hello\r\n\r\n").status, 400);
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    fmt::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::Response;
+use crate::{
+    config::Visibility,
+    state::{ServiceState, ServiceStatus, StatusBoard},
+};
+
+/// Most incidents listed on the page
+const MAX_INCIDENTS: usize = 20;
+
+/// Seconds between reloads of the page by browsers
+const REFRESH_SECS: u32 = 60;
+
+const STYLE: &str = "\
+body{font-family:system-ui,sans-serif;max-width:48rem;margin:2rem auto;padding:0 1rem;color:#222}\
+h1{font-size:1.6rem}h2{font-size:1.1rem;margin-top:2rem}\
+ul{list-style:none;padding:0;margin:0}\
+li{display:flex;gap:1rem;align-items:center;padding:.6rem .8rem;border-bottom:1px solid #e4e4e4}\
+.name{flex:1;font-weight:600}.uptime{color:#666;font-size:.9rem}\
+.status{font-size:.8rem;font-weight:700;padding:.2rem .5rem;border-radius:.3rem;color:#fff}\
+.banner{padding:1rem;border-radius:.4rem;color:#fff;font-weight:600}\
+.up{background:#2e9d4c}.degraded{background:#d99a06}.down{background:#cf3a3a}.unknown{background:#888}\
+.incidents li{display:block}.time{color:#666;font-size:.9rem}\
+footer{margin-top:2rem;color:#888;font-size:.8rem}";
+
+/// Escapes text for HTML element content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Formats a time like `2023-11-14 22:13 UTC`
+fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    // civil date from days since the epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+/// Formats a duration by its two largest units, like `3d 4h` or `5m 2s`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let units = [
+        (secs / 86_400, "d"),
+        (secs % 86_400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+    ];
+    let first = units.iter().position(|(n, _)| *n > 0).unwrap_or(3);
+    units[first..]
+        .iter()
+        .take(2)
+        .filter(|(n, _)| *n > 0 || first == 3)
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn css_class(status: ServiceStatus) -> String {
+    status.to_string().to_lowercase()
+}
+
+/// A time a service was down or degraded
+struct Incident<'a> {
+    service: &'a str,
+    state: &'a ServiceState,
+    /// None while it is ongoing
+    end: Option<SystemTime>,
+}
+
+/// Incidents of the public services that ended within the history, latest
+/// first
+fn incidents<'a>(board: &'a StatusBoard, public: &[usize], start: SystemTime) -> Vec<Incident<'a>> {
+    let mut incidents = Vec::new();
+    for &i in public {
+        let current = board.service(i);
+        let history = board.history(i);
+        let ends = history
+            .iter()
+            .skip(1)
+            .map(|s| Some(s.since))
+            .chain(std::iter::once(Some(current.since)));
+        let states = history
+            .iter()
+            .zip(ends)
+            .chain(std::iter::once((current, None)));
+        for (state, end) in states {
+            let problem = matches!(state.status, ServiceStatus::Down | ServiceStatus::Degraded);
+            if problem && end.map_or(true, |end| end > start) {
+                incidents.push(Incident {
+                    service: &board.config().services[i].name,
+                    state,
+                    end,
+                });
+            }
+        }
+    }
+    incidents.sort_by_key(|i| std::cmp::Reverse(i.state.since));
+    incidents.truncate(MAX_INCIDENTS);
+    incidents
+}
+
+/// Renders the status page of the public services. Hosts and failure
+/// reasons are left out since the page is meant to be seen by anyone.
+pub(super) fn render(board: &StatusBoard, now: SystemTime) -> Response {
+    let config = board.config();
+    let title = config
+        .server
+        .as_ref()
+        .map_or("Service status", |s| s.title.as_str());
+    let public: Vec<usize> = (0..config.services.len())
+        .filter(|&i| config.services[i].visibility == Visibility::Public)
+        .collect();
+    let window = board.history_window();
+    let start = now.checked_sub(window).unwrap_or(UNIX_EPOCH);
+
+    let mut out = String::new();
+    write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <meta http-equiv=\"refresh\" content=\"{}\">\n<title>{}</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        REFRESH_SECS,
+        escape(title),
+        STYLE,
+        escape(title)
+    )
+    .unwrap();
+
+    let statuses: Vec<ServiceStatus> = public.iter().map(|&i| board.service(i).status).collect();
+    let (overall, banner) = if statuses.contains(&ServiceStatus::Down) {
+        (ServiceStatus::Down, "Some services are down")
+    } else if statuses.contains(&ServiceStatus::Degraded) {
+        (ServiceStatus::Degraded, "Some services are degraded")
+    } else if statuses.contains(&ServiceStatus::Unknown) {
+        (ServiceStatus::Unknown, "Waiting for results")
+    } else {
+        (ServiceStatus::Up, "All services are up")
+    };
+    writeln!(
+        out,
+        "<p class=\"banner {}\">{}</p>",
+        css_class(overall),
+        banner
+    )
+    .unwrap();
+
+    // groups in the order they first appear, services without one first
+    let mut groups: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+    for &i in &public {
+        let group = config.services[i].group.as_deref();
+        match groups.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, services)) => services.push(i),
+            None => groups.push((group, vec![i])),
+        }
+    }
+    groups.sort_by_key(|(group, _)| group.is_some());
+    for (group, services) in &groups {
+        if let Some(group) = group {
+            writeln!(out, "<h2>{}</h2>", escape(group)).unwrap();
+        }
+        out.push_str("<ul>\n");
+        for &i in services {
+            let state = board.service(i);
+            let uptime = board
+                .uptime(i, start, now)
+                .map_or_else(|| "no data".to_owned(), |u| format!("{:.2}%", u * 100.0));
+            writeln!(
+                out,
+                "<li><span class=\"name\">{}</span>\
+                 <span class=\"uptime\" title=\"uptime over the last {}\">{}</span>\
+                 <span class=\"status {}\" title=\"since {}\">{}</span></li>",
+                escape(&config.services[i].name),
+                format_duration(window),
+                uptime,
+                css_class(state.status),
+                format_time(state.since),
+                state.status
+            )
+            .unwrap();
+        }
+        out.push_str("</ul>\n");
+    }
+
+    out.push_str("<h2>Incidents</h2>\n");
+    let incidents = incidents(board, &public, start);
+    if incidents.is_empty() {
+        writeln!(
+            out,
+            "<p>No incidents in the last {}.</p>",
+            format_duration(window)
+        )
+        .unwrap();
+    } else {
+        out.push_str("<ul class=\"incidents\">\n");
+        for incident in &incidents {
+            let status = incident.state.status;
+            let since = incident.state.since;
+            let text = match incident.end {
+                Some(end) => format!(
+                    "was {} for {}",
+                    status,
+                    format_duration(end.duration_since(since).unwrap_or_default())
+                ),
+                None => format!("is {}", status),
+            };
+            writeln!(
+                out,
+                "<li><span class=\"status {}\">{}</span> <b>{}</b> {} \
+                 <span class=\"time\">since {}</span></li>",
+                css_class(status),
+                status,
+                escape(incident.service),
+                text,
+                format_time(since)
+            )
+            .unwrap();
+        }
+        out.push_str("</ul>\n");
+    }
+
+    writeln!(
+        out,
+        "<footer>Updated {}</footer>\n</body>\n</html>",
+        format_time(now)
+    )
+    .unwrap();
+    Response {
+        status: 200,
+        content_type: "text/html; charset=utf-8",
+        body: out.into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{
+        check::{CheckResult, Failure},
+        config::Config,
+        scheduler::Report,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00 UTC");
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(format_time(time), "2023-11-14 22:13 UTC");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_time(leap_day), "2000-02-29 00:00 UTC");
+
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "1h");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 86_400 + 4 * 3600 + 5)),
+            "3d 4h"
+        );
+    }
+
+    #[test]
+    fn renders_public_services() {
+        let config = r###"
+            services:
+            - name: Checkout <beta>
+              host: shop.example.com
+              group: Shop
+              health:
+                - method: ping
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: ping
+            - name: Internal
+              host: internal.example.com
+              visibility: private
+              health:
+                - method: ping
+            server:
+              listen: 127.0.0.1:8080
+              title: Foo & Co
+        "###;
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let mut board = StatusBoard::new(config, UNIX_EPOCH);
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut update = |service, secs, failure| {
+            let result = match failure {
+                Some(failure) => CheckResult::failure(Duration::from_millis(5), failure),
+                None => CheckResult::success(Duration::from_millis(5)),
+            };
+            board.update(&Report {
+                service,
+                check: 0,
+                time: at(secs),
+                result,
+            });
+        };
+        update(0, 100, None);
+        update(0, 400, Some(Failure::Timeout));
+        update(0, 500, None);
+        update(1, 100, Some(Failure::Status(500)));
+        update(2, 100, Some(Failure::Timeout));
+
+        let response = render(&board, at(600));
+        assert_eq!(response.content_type, "text/html; charset=utf-8");
+        let html = String::from_utf8(response.body).unwrap();
+        assert!(html.contains("<title>Foo &amp; Co</title>"));
+        assert!(html.contains("<p class=\"banner down\">Some services are down</p>"));
+        assert!(!html.contains("Internal"));
+        assert!(!html.contains("example.com"));
+        assert!(!html.contains("status code 500"));
+
+        // services without a group come first
+        let foo = html.find("Foo Web Service").unwrap();
+        let shop = html.find("<h2>Shop</h2>").unwrap();
+        assert!(foo < shop);
+        assert!(html.contains("<span class=\"name\">Checkout &lt;beta&gt;</span>"));
+        assert!(html.contains(">80.00%</span>"));
+        assert!(html.contains(">0.00%</span>"));
+
+        // latest incidents first
+        let past = html
+            .find("<b>Checkout &lt;beta&gt;</b> was DOWN for 1m 40s")
+            .unwrap();
+        let ongoing = html.find("<b>Foo Web Service</b> is DOWN").unwrap();
+        assert!(past < ongoing);
+        assert!(html.contains("<footer>Updated 1970-01-01 00:10 UTC</footer>"));
+        assert!(html.contains("title=\"uptime over the last 30d\""));
+    }
+
+    #[test]
+    fn covers_the_retention_of_the_history() {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: ping
+            history:
+              path: /var/lib/griffin/history.jsonl
+              retention: 1h
+        "###;
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let mut board = StatusBoard::new(config, UNIX_EPOCH);
+        for (secs, failure) in [(100, Some(Failure::Timeout)), (200, None)] {
+            let result = match failure {
+                Some(failure) => CheckResult::failure(Duration::from_millis(5), failure),
+                None => CheckResult::success(Duration::from_millis(5)),
+            };
+            board.update(&Report {
+                service: 0,
+                check: 0,
+                time: UNIX_EPOCH + Duration::from_secs(secs),
+                result,
+            });
+        }
+
+        // the outage ended before the last hour
+        let html = render(&board, UNIX_EPOCH + Duration::from_secs(7200));
+        let html = String::from_utf8(html.body).unwrap();
+        assert!(html.contains("title=\"uptime over the last 1h\">100.00%</span>"));
+        assert!(html.contains("<p>No incidents in the last 1h.</p>"));
+    }
+}
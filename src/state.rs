@@ -0,0 +1,705 @@
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    check::{CheckResult, Status},
+    config::{Config, HealthCheck, Service, StatusPolicy},
+    scheduler::Report,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Overall health of a service
+pub enum ServiceStatus {
+    /// Every check is passing
+    Up,
+    /// Some checks are failing or warning but not enough to bring the
+    /// service down under its policy
+    Degraded,
+    Down,
+    /// Not enough checks have results to tell
+    Unknown,
+}
+
+impl Display for ServiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceStatus::Up => write!(f, "UP"),
+            ServiceStatus::Degraded => write!(f, "DEGRADED"),
+            ServiceStatus::Down => write!(f, "DOWN"),
+            ServiceStatus::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Status of a service, when it last changed and why
+pub struct ServiceState {
+    pub status: ServiceStatus,
+    pub since: SystemTime,
+    /// Checks that are failing or still unknown, None while the service is up
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Whether a check is passing, changing only after enough runs in a row
+/// agree so a single dropped packet doesn't flip it
+pub struct CheckState {
+    failure_threshold: u32,
+    success_threshold: u32,
+    /// None until enough runs agreed for the first time
+    passing: Option<bool>,
+    /// Outcome of the latest run and how many runs in a row had it
+    streak: Option<(bool, u32)>,
+}
+
+impl CheckState {
+    /// Creates the state of a check that has not run yet
+    pub fn new(failure_threshold: u32, success_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            success_threshold: success_threshold.max(1),
+            passing: None,
+            streak: None,
+        }
+    }
+
+    /// Creates the state of a check using its configured thresholds
+    pub fn for_check(check: &HealthCheck) -> Self {
+        Self::new(check.failure_threshold, check.success_threshold)
+    }
+
+    /// Records the outcome of a run. Returns true if the check changed state.
+    pub fn record(&mut self, success: bool) -> bool {
+        let count = match self.streak {
+            Some((last, count)) if last == success => count + 1,
+            _ => 1,
+        };
+        self.streak = Some((success, count));
+        let threshold = if success {
+            self.success_threshold
+        } else {
+            self.failure_threshold
+        };
+        if count >= threshold && self.passing != Some(success) {
+            self.passing = Some(success);
+            return true;
+        }
+        false
+    }
+
+    /// Whether the check is passing, None while its state is not known yet
+    pub fn passing(&self) -> Option<bool> {
+        self.passing
+    }
+}
+
+/// How long past states of a service are kept when the config has no
+/// history
+pub const HISTORY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Upper bounds of the buckets runs are counted in by latency
+pub const LATENCY_BUCKETS: [Duration; 11] = [
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Counts of the runs of a check since griffin started
+pub struct CheckStats {
+    /// Runs by status in the order ok, warning, critical, unknown
+    runs: [u64; 4],
+    /// Runs by latency, one count per bucket of `LATENCY_BUCKETS` and a last
+    /// one for slower runs
+    latencies: [u64; LATENCY_BUCKETS.len() + 1],
+    /// Latency of every run added up
+    pub total_latency: Duration,
+}
+
+impl CheckStats {
+    fn record(&mut self, result: &CheckResult) {
+        self.runs[Self::index(result.status)] += 1;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| result.latency <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latencies[bucket] += 1;
+        self.total_latency += result.latency;
+    }
+
+    fn index(status: Status) -> usize {
+        match status {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+
+    /// Runs that ended with a status
+    pub fn runs(&self, status: Status) -> u64 {
+        self.runs[Self::index(status)]
+    }
+
+    /// Runs in total
+    pub fn count(&self) -> u64 {
+        self.runs.iter().sum()
+    }
+
+    /// Runs that took at most each bound of `LATENCY_BUCKETS`
+    pub fn latency_buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        LATENCY_BUCKETS
+            .iter()
+            .zip(self.latencies.iter().scan(0, |total, n| {
+                *total += n;
+                Some(*total)
+            }))
+            .map(|(bound, count)| (*bound, count))
+    }
+}
+
+impl StatusPolicy {
+    /// Whether a service is down given the weight of each of its checks and
+    /// whether it is passing
+    fn is_down(self, checks: &[(u32, bool)]) -> bool {
+        let passing = checks.iter().filter(|(_, passing)| *passing);
+        match self {
+            StatusPolicy::All => checks.iter().any(|(_, passing)| !passing),
+            StatusPolicy::Any => passing.count() == 0,
+            StatusPolicy::Quorum(n) => passing.count() < n as usize,
+            StatusPolicy::Weighted(fraction) => {
+                let total: u32 = checks.iter().map(|(weight, _)| weight).sum();
+                let passing: u32 = passing.map(|(weight, _)| weight).sum();
+                f64::from(passing) < fraction * f64::from(total)
+            }
+        }
+    }
+}
+
+/// Works out the status of a service from the latest result and state of
+/// each of its checks.
+///
+/// Checks that are not known yet make the service unknown only when they
+/// could decide whether it is down.
+fn aggregate(
+    service: &Service,
+    reports: &[Option<Report>],
+    checks: &[CheckState],
+) -> (ServiceStatus, Option<String>) {
+    let passing: Vec<Option<bool>> = checks.iter().map(CheckState::passing).collect();
+    if passing.iter().all(Option::is_none) {
+        return (ServiceStatus::Unknown, Some("no results yet".to_owned()));
+    }
+
+    let mut problems = Vec::new();
+    let mut unknown = 0;
+    let results = reports.iter().map(|r| r.as_ref().map(|r| &r.result));
+    for ((check, result), passing) in service.health.iter().zip(results).zip(&passing) {
+        match (result, passing) {
+            (Some(result), Some(false)) => problems.push(match &result.failure {
+                Some(failure) => format!("{} check failed: {}", check.method, failure),
+                // passing again, but not often enough in a row yet
+                None if result.status == Status::Ok => {
+                    format!("{} check is recovering", check.method)
+                }
+                None => format!("{} check failed: {}", check.method, result.status),
+            }),
+            (Some(result), Some(true)) if result.status == Status::Warning => {
+                problems.push(format!("{} check is warning", check.method))
+            }
+            (_, None) => unknown += 1,
+            _ => {}
+        }
+    }
+    let reason = Some(problems.join("; "));
+
+    let weights = |unknown_passing: bool| -> Vec<(u32, bool)> {
+        service
+            .health
+            .iter()
+            .zip(&passing)
+            .map(|(check, passing)| (check.weight, passing.unwrap_or(unknown_passing)))
+            .collect()
+    };
+    if service.policy.is_down(&weights(true)) {
+        (ServiceStatus::Down, reason)
+    } else if service.policy.is_down(&weights(false)) {
+        let waiting = format!("waiting for {} of {} checks", unknown, checks.len());
+        problems.push(waiting);
+        (ServiceStatus::Unknown, Some(problems.join("; ")))
+    } else if !problems.is_empty() {
+        (ServiceStatus::Degraded, reason)
+    } else {
+        (ServiceStatus::Up, None)
+    }
+}
+
+/// Latest reports of every check and the status they add up to for every
+/// service of a config
+pub struct StatusBoard {
+    config: Arc<Config>,
+    reports: Vec<Vec<Option<Report>>>,
+    checks: Vec<Vec<CheckState>>,
+    stats: Vec<Vec<CheckStats>>,
+    services: Vec<ServiceState>,
+    /// Past states of every service, oldest first
+    history: Vec<Vec<ServiceState>>,
+}
+
+impl StatusBoard {
+    /// Creates a board where every service is unknown since `now`
+    pub fn new(config: Arc<Config>, now: SystemTime) -> Self {
+        let reports = config
+            .services
+            .iter()
+            .map(|s| vec![None; s.health.len()])
+            .collect();
+        let checks = config
+            .services
+            .iter()
+            .map(|s| s.health.iter().map(CheckState::for_check).collect())
+            .collect();
+        let stats = config
+            .services
+            .iter()
+            .map(|s| vec![CheckStats::default(); s.health.len()])
+            .collect();
+        let history = vec![Vec::new(); config.services.len()];
+        let services = config
+            .services
+            .iter()
+            .map(|_| ServiceState {
+                status: ServiceStatus::Unknown,
+                since: now,
+                reason: Some("no results yet".to_owned()),
+            })
+            .collect();
+        Self {
+            config,
+            reports,
+            checks,
+            stats,
+            services,
+            history,
+        }
+    }
+
+    /// Records a report and updates the status of its service. Returns the
+    /// previous status if it changed.
+    pub fn update(&mut self, report: &Report) -> Option<ServiceStatus> {
+        self.stats[report.service][report.check].record(&report.result);
+        self.replay(report)
+    }
+
+    /// Updates the status of a service with a report from the history. The
+    /// run counters are left alone since they cover this process only.
+    pub fn replay(&mut self, report: &Report) -> Option<ServiceStatus> {
+        let reports = &mut self.reports[report.service];
+        reports[report.check] = Some(report.clone());
+        let checks = &mut self.checks[report.service];
+        checks[report.check].record(report.result.is_success());
+
+        let service = &self.config.services[report.service];
+        let (status, reason) = aggregate(service, reports, checks);
+        let state = &mut self.services[report.service];
+        if state.status == status {
+            state.reason = reason;
+            return None;
+        }
+        let previous = state.status;
+        let past = std::mem::replace(
+            state,
+            ServiceState {
+                status,
+                since: report.time,
+                reason,
+            },
+        );
+        // keep the state the service was in when the history starts
+        let start = report
+            .time
+            .checked_sub(self.history_window())
+            .unwrap_or(UNIX_EPOCH);
+        let history = &mut self.history[report.service];
+        history.push(past);
+        let expired = history
+            .iter()
+            .skip(1)
+            .take_while(|s| s.since <= start)
+            .count();
+        history.drain(..expired);
+        Some(previous)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// State of the service at an index of the config
+    pub fn service(&self, service: usize) -> &ServiceState {
+        &self.services[service]
+    }
+
+    /// How long past states are kept, which is the retention of the history
+    /// so the board covers what the store replays
+    pub fn history_window(&self) -> Duration {
+        self.config
+            .history
+            .as_ref()
+            .map_or(HISTORY, |h| h.retention.as_duration())
+    }
+
+    /// Past states of the service at an index of the config, oldest first.
+    /// States that ended more than the history window ago are dropped.
+    pub fn history(&self, service: usize) -> &[ServiceState] {
+        &self.history[service]
+    }
+
+    /// Share of the time from `start` to `now` a service was up or degraded,
+    /// leaving out the time its status was unknown. None if it was unknown
+    /// all along.
+    pub fn uptime(&self, service: usize, start: SystemTime, now: SystemTime) -> Option<f64> {
+        let states = self.history[service]
+            .iter()
+            .chain(std::iter::once(&self.services[service]));
+        let ends = self.history[service]
+            .iter()
+            .skip(1)
+            .map(|s| s.since)
+            .chain(std::iter::once(self.services[service].since))
+            .chain(std::iter::once(now));
+        let (mut up, mut known) = (Duration::ZERO, Duration::ZERO);
+        for (state, end) in states.zip(ends) {
+            let from = state.since.max(start);
+            let time = end.duration_since(from).unwrap_or_default();
+            match state.status {
+                ServiceStatus::Up | ServiceStatus::Degraded => {
+                    up += time;
+                    known += time;
+                }
+                ServiceStatus::Down => known += time,
+                ServiceStatus::Unknown => {}
+            }
+        }
+        if known.is_zero() {
+            return None;
+        }
+        Some(up.as_secs_f64() / known.as_secs_f64())
+    }
+
+    /// Latest report of a check of a service, None until it has run
+    pub fn report(&self, service: usize, check: usize) -> Option<&Report> {
+        self.reports[service][check].as_ref()
+    }
+
+    /// State of a check of a service
+    pub fn check(&self, service: usize, check: usize) -> &CheckState {
+        &self.checks[service][check]
+    }
+
+    /// Counts of the runs of a check of a service
+    pub fn stats(&self, service: usize, check: usize) -> &CheckStats {
+        &self.stats[service][check]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::check::{CheckResult, Failure};
+    use std::time::Duration;
+
+    /// Board for a service with three checks. The http check weighs 3 when
+    /// the policy is weighted.
+    fn new_board(policy: &str) -> StatusBoard {
+        let weight = if policy.contains("weighted") { 3 } else { 1 };
+        let config = format!(
+            r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              policy: {}
+              health:
+                - method: http
+                  endpoint: /status
+                  weight: {}
+                - method: ping
+                - method: tcp
+                  port: 22
+            "###,
+            policy, weight
+        );
+        StatusBoard::new(
+            Arc::new(Config::new(config.as_bytes()).unwrap()),
+            SystemTime::UNIX_EPOCH,
+        )
+    }
+
+    fn report(check: usize, failure: Option<Failure>) -> Report {
+        Report {
+            service: 0,
+            check,
+            time: SystemTime::now(),
+            result: match failure {
+                Some(failure) => CheckResult::failure(Duration::from_millis(5), failure),
+                None => CheckResult::success(Duration::from_millis(5)),
+            },
+        }
+    }
+
+    fn status(board: &StatusBoard) -> ServiceStatus {
+        board.service(0).status
+    }
+
+    #[test]
+    fn all_checks_must_pass_by_default() {
+        let mut board = new_board("all");
+        assert_eq!(status(&board), ServiceStatus::Unknown);
+        assert_eq!(board.update(&report(0, None)), None);
+        assert_eq!(board.update(&report(1, None)), None);
+        assert_eq!(board.update(&report(2, None)), Some(ServiceStatus::Unknown));
+        assert_eq!(status(&board), ServiceStatus::Up);
+        assert_eq!(board.service(0).reason, None);
+
+        assert_eq!(
+            board.update(&report(1, Some(Failure::Timeout))),
+            Some(ServiceStatus::Up)
+        );
+        assert_eq!(status(&board), ServiceStatus::Down);
+        assert_eq!(
+            board.service(0).reason.as_deref(),
+            Some("ping check failed: timed out")
+        );
+        assert!(board.service(0).since > SystemTime::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn fails_right_away_when_unknown_checks_cannot_help() {
+        let mut board = new_board("all");
+        assert_eq!(
+            board.update(&report(0, Some(Failure::Status(500)))),
+            Some(ServiceStatus::Unknown)
+        );
+        assert_eq!(status(&board), ServiceStatus::Down);
+
+        let mut board = new_board("any");
+        assert_eq!(board.update(&report(0, Some(Failure::Status(500)))), None);
+        assert_eq!(
+            board.service(0).reason.as_deref(),
+            Some("http check failed: unexpected status code 500; waiting for 2 of 3 checks")
+        );
+        board.update(&report(1, None));
+        assert_eq!(status(&board), ServiceStatus::Degraded);
+    }
+
+    #[test]
+    fn counts_passing_checks_for_quorum() {
+        let mut board = new_board("{quorum: 2}");
+        board.update(&report(0, None));
+        board.update(&report(1, Some(Failure::Timeout)));
+        assert_eq!(status(&board), ServiceStatus::Unknown);
+        board.update(&report(2, None));
+        assert_eq!(status(&board), ServiceStatus::Degraded);
+        board.update(&report(2, Some(Failure::Timeout)));
+        assert_eq!(status(&board), ServiceStatus::Down);
+        assert_eq!(
+            board.service(0).reason.as_deref(),
+            Some("ping check failed: timed out; tcp check failed: timed out")
+        );
+    }
+
+    #[test]
+    fn weighs_passing_checks() {
+        let mut board = new_board("{weighted: 0.6}");
+        board.update(&report(0, None));
+        assert_eq!(status(&board), ServiceStatus::Up);
+        board.update(&report(1, Some(Failure::Timeout)));
+        board.update(&report(2, Some(Failure::Timeout)));
+        assert_eq!(status(&board), ServiceStatus::Degraded);
+        board.update(&report(0, Some(Failure::Timeout)));
+        board.update(&report(1, None));
+        board.update(&report(2, None));
+        assert_eq!(status(&board), ServiceStatus::Down);
+    }
+
+    #[test]
+    fn counts_runs() {
+        let mut board = new_board("all");
+        board.update(&report(1, None));
+        board.update(&report(1, Some(Failure::Timeout)));
+        let mut slow = report(1, None);
+        slow.result.latency = Duration::from_secs(30);
+        board.update(&slow);
+
+        let stats = board.stats(0, 1);
+        assert_eq!(stats.count(), 3);
+        assert_eq!(stats.runs(Status::Ok), 2);
+        assert_eq!(stats.runs(Status::Critical), 1);
+        assert_eq!(stats.total_latency, Duration::from_millis(30_010));
+        let buckets: Vec<u64> = stats.latency_buckets().map(|(_, n)| n).collect();
+        assert_eq!(buckets, vec![2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2]);
+        assert_eq!(board.stats(0, 0).count(), 0);
+    }
+
+    fn report_at(check: usize, secs: u64, failure: Option<Failure>) -> Report {
+        Report {
+            time: UNIX_EPOCH + Duration::from_secs(secs),
+            ..report(check, failure)
+        }
+    }
+
+    #[test]
+    fn keeps_history_and_uptime() {
+        let mut board = new_board("all");
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(board.uptime(0, at(0), at(100)), None);
+
+        for check in 0..3 {
+            board.update(&report_at(check, 100, None));
+        }
+        board.update(&report_at(0, 400, Some(Failure::Timeout)));
+        board.update(&report_at(0, 500, None));
+        let history: Vec<(ServiceStatus, SystemTime)> = board
+            .history(0)
+            .iter()
+            .map(|s| (s.status, s.since))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (ServiceStatus::Unknown, UNIX_EPOCH),
+                (ServiceStatus::Up, at(100)),
+                (ServiceStatus::Down, at(400)),
+            ]
+        );
+        assert_eq!(board.service(0).status, ServiceStatus::Up);
+
+        // up from 100 to 400 and from 500 to 600, down from 400 to 500
+        assert_eq!(board.uptime(0, at(0), at(600)), Some(0.8));
+        assert_eq!(board.uptime(0, at(450), at(600)), Some(100.0 / 150.0));
+
+        // only the up state from 500 ended within the history
+        let month_later = 500 + HISTORY.as_secs() + 50;
+        board.update(&report_at(0, month_later, Some(Failure::Timeout)));
+        let history: Vec<SystemTime> = board.history(0).iter().map(|s| s.since).collect();
+        assert_eq!(history, vec![at(500)]);
+    }
+
+    #[test]
+    fn warnings_degrade_a_service() {
+        let mut board = new_board("all");
+        board.update(&report(0, None));
+        board.update(&report(1, None));
+        let mut warning = report(2, None);
+        warning.result.status = Status::Warning;
+        board.update(&warning);
+        assert_eq!(status(&board), ServiceStatus::Degraded);
+        assert_eq!(
+            board.service(0).reason.as_deref(),
+            Some("tcp check is warning")
+        );
+    }
+
+    #[test]
+    fn replays_without_counting_runs() {
+        let mut board = new_board("any");
+        let mut replayed = report(1, None);
+        replayed.result.failure = Some(Failure::Recorded("timed out".to_owned()));
+        replayed.result.status = Status::Critical;
+        board.replay(&replayed);
+        assert_eq!(
+            board.service(0).reason.as_deref(),
+            Some("ping check failed: timed out; waiting for 2 of 3 checks")
+        );
+        assert_eq!(board.stats(0, 1).count(), 0);
+
+        board.update(&report(1, None));
+        assert_eq!(board.stats(0, 1).count(), 1);
+        assert_eq!(board.stats(0, 1).runs(Status::Ok), 1);
+    }
+
+    #[test]
+    fn recovering_checks_keep_a_service_down() {
+        let config = r###"
+            services:
+            - name: Foo Web Service
+              host: foo.example.com
+              health:
+                - method: ping
+                  success_threshold: 2
+        "###;
+        let config = Arc::new(Config::new(config.as_bytes()).unwrap());
+        let mut board = StatusBoard::new(config, SystemTime::UNIX_EPOCH);
+        board.update(&report(0, Some(Failure::Timeout)));
+        board.update(&report(0, None));
+        assert_eq!(status(&board), ServiceStatus::Down);
+        assert_eq!(
+            board.service(0).reason.as_deref(),
+            Some("ping check is recovering")
+        );
+        board.update(&report(0, None));
+        assert_eq!(status(&board), ServiceStatus::Up);
+    }
+
+    #[test]
+    fn flips_after_consecutive_runs() {
+        let mut state = CheckState::new(3, 2);
+        assert_eq!(state.passing(), None);
+        assert!(!state.record(true));
+        assert!(state.record(true));
+        assert_eq!(state.passing(), Some(true));
+
+        // failures must come in a row
+        assert!(!state.record(false));
+        assert!(!state.record(false));
+        assert!(!state.record(true));
+        assert!(!state.record(false));
+        assert!(!state.record(false));
+        assert!(state.record(false));
+        assert_eq!(state.passing(), Some(false));
+        assert!(!state.record(false));
+
+        assert!(!state.record(true));
+        assert!(state.record(true));
+        assert_eq!(state.passing(), Some(true));
+    }
+
+    #[test]
+    fn flips_right_away_with_default_thresholds() {
+        let mut state = CheckState::new(1, 1);
+        assert!(state.record(false));
+        assert_eq!(state.passing(), Some(false));
+        assert!(state.record(true));
+        assert!(!state.record(true));
+
+        // a threshold of 0 behaves like 1
+        let mut state = CheckState::new(0, 0);
+        assert!(state.record(true));
+    }
+
+    #[test]
+    fn starts_unknown_until_threshold_is_met() {
+        let mut state = CheckState::new(2, 3);
+        assert!(!state.record(false));
+        assert!(!state.record(true));
+        assert!(!state.record(true));
+        assert_eq!(state.passing(), None);
+        assert!(state.record(true));
+        assert_eq!(state.passing(), Some(true));
+    }
+}